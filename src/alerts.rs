@@ -0,0 +1,242 @@
+use crate::report::format_time;
+use crate::stats::SystemStats;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+pub struct Thresholds {
+    pub cpu: f64,
+    pub memory: f64,
+    pub disk: f64, // for mounts that no entry in `disk_by_mount` matches
+    pub disk_by_mount: Vec<(String, f64)>, // (mount pattern, percent), from `disk_thresholds` in the config
+}
+
+impl Thresholds {
+    /// The disk threshold for `mount`: an exact entry wins, then the longest matching pattern.
+    pub fn disk_for(&self, mount: &str) -> f64 {
+        self.disk_by_mount
+            .iter()
+            .filter(|(pattern, _)| glob_matches(pattern, mount))
+            .max_by_key(|(pattern, _)| (pattern == mount, pattern.len()))
+            .map_or(self.disk, |(_, percent)| *percent)
+    }
+}
+
+// Shell-style matching: `*` matches any run of characters, slashes included, and `?` any one
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut star = None; // where to resume after the last `*` if the rest fails to match
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((after, matched)) => {
+                    star = Some((after, matched + 1));
+                    p = after;
+                    t = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[derive(Clone)]
+pub struct Alert {
+    pub metric: String,
+    pub value: f64,
+    pub threshold: f64,
+}
+
+impl Alert {
+    fn message(&self, host: &str) -> String {
+        format!(
+            "{}: {} at {:.1}% (threshold {:.0}%)",
+            host, self.metric, self.value, self.threshold
+        )
+    }
+}
+
+pub fn check_thresholds(stats: &SystemStats, thresholds: &Thresholds) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+    if stats.cpu_usage >= thresholds.cpu {
+        alerts.push(Alert { metric: "cpu".to_string(), value: stats.cpu_usage, threshold: thresholds.cpu });
+    }
+    if stats.memory_total > 0 && stats.memory_percent() >= thresholds.memory {
+        alerts.push(Alert {
+            metric: "memory".to_string(),
+            value: stats.memory_percent(),
+            threshold: thresholds.memory,
+        });
+    }
+    for (mount, total, used) in &stats.disk_usage {
+        let percent = *used as f64 / *total as f64 * 100.0;
+        let threshold = thresholds.disk_for(mount);
+        if *total > 0 && percent >= threshold {
+            alerts.push(Alert { metric: format!("disk:{}", mount), value: percent, threshold });
+        }
+    }
+    alerts
+}
+
+// Result of the `check` command; the exit codes follow the Nagios plugin convention
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warning, // thresholds pass, but --fail-on-warning is on and something was warned about
+    Critical,
+    Unknown,
+}
+
+impl CheckStatus {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            CheckStatus::Ok => 0,
+            CheckStatus::Warning => 1,
+            CheckStatus::Critical => 2,
+            CheckStatus::Unknown => 3,
+        }
+    }
+}
+
+pub fn health_check(stats: &SystemStats, thresholds: &Thresholds) -> (CheckStatus, serde_json::Value) {
+    let failing: Vec<String> = check_thresholds(stats, thresholds)
+        .iter()
+        .map(|alert| format!("{}={:.0}", alert.metric, alert.value))
+        .collect();
+    let status = if failing.is_empty() { CheckStatus::Ok } else { CheckStatus::Critical };
+    let report = serde_json::json!({
+        "status": status,
+        "failing": failing,
+        "stats": stats,
+    });
+    (status, report)
+}
+
+pub async fn send_webhook_alert(client: reqwest::Client, url: String, host: String, alert: Alert) -> Result<()> {
+    let text = alert.message(&host);
+    // `text` is what Slack reads and `content` is what Discord reads; generic receivers get the fields
+    let payload = serde_json::json!({
+        "host": host,
+        "metric": alert.metric,
+        "value": alert.value,
+        "threshold": alert.threshold,
+        "timestamp": format_time(chrono::Utc::now(), "%+"),
+        "text": text,
+        "content": text,
+    });
+    client.post(&url).json(&payload).send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// `monitor --alert-log`: every alert fired, appended as one JSON object per line with the same
+/// fields as the webhook payload, for an audit trail that outlives the session.
+pub struct AlertLog {
+    file: File,
+}
+
+impl AlertLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open alert log {}", path.display()))?;
+        Ok(AlertLog { file })
+    }
+
+    pub fn record(&self, host: &str, alert: &Alert) -> Result<()> {
+        let entry = serde_json::json!({
+            "timestamp": format_time(chrono::Utc::now(), "%+"),
+            "host": host,
+            "metric": alert.metric,
+            "value": alert.value,
+            "threshold": alert.threshold,
+        });
+        // One write per line, so entries from monitors sharing a log don't interleave
+        (&self.file).write_all(format!("{}\n", entry).as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn thresholds_flag_each_breached_metric() {
+        let stats = healthy_stats();
+        let thresholds = Thresholds { cpu: 90.0, memory: 20.0, disk: 90.0, disk_by_mount: Vec::new() };
+        let metrics: Vec<String> = check_thresholds(&stats, &thresholds).into_iter().map(|a| a.metric).collect();
+        assert_eq!(metrics, ["memory", "disk:/data"]);
+    }
+
+    #[test]
+    fn health_check_reports_failing_metrics_as_json() {
+        let stats = healthy_stats();
+        let (status, report) = health_check(&stats, &Thresholds { cpu: 90.0, memory: 85.0, disk: 90.0, disk_by_mount: Vec::new() });
+        assert_eq!(status, CheckStatus::Critical);
+        assert_eq!(status.exit_code(), 2);
+        assert_eq!(report["status"], "critical");
+        assert_eq!(report["failing"], serde_json::json!(["disk:/data=95"]));
+        assert_eq!(report["stats"]["memory_total"], 8_000_000_000u64);
+
+        let (status, _) = health_check(&stats, &Thresholds { cpu: 90.0, memory: 85.0, disk: 99.0, disk_by_mount: Vec::new() });
+        assert_eq!(status, CheckStatus::Ok);
+        assert_eq!(CheckStatus::Warning.exit_code(), 1);
+        assert_eq!(serde_json::json!(CheckStatus::Warning), "warning");
+    }
+
+    #[test]
+    fn per_mount_disk_thresholds_pick_the_most_specific_rule() {
+        let disk_by_mount = [("/", 85.0), ("/backup*", 98.0), ("/backup", 99.0), ("/mnt/?", 70.0)]
+            .map(|(pattern, percent)| (pattern.to_string(), percent))
+            .to_vec();
+        let thresholds = Thresholds { cpu: 90.0, memory: 90.0, disk: 90.0, disk_by_mount };
+        assert_eq!(thresholds.disk_for("/"), 85.0);
+        assert_eq!(thresholds.disk_for("/backup"), 99.0);
+        assert_eq!(thresholds.disk_for("/backup/daily"), 98.0);
+        assert_eq!(thresholds.disk_for("/mnt/a"), 70.0);
+        assert_eq!(thresholds.disk_for("/mnt/ab"), 90.0);
+
+        // /data is at 95%, which only a stricter rule for it flags
+        let stats = healthy_stats();
+        assert!(check_thresholds(&stats, &Thresholds { disk: 99.0, ..thresholds }).is_empty());
+        let thresholds = Thresholds { cpu: 90.0, memory: 90.0, disk: 99.0, disk_by_mount: vec![("/d*".to_string(), 95.0)] };
+        let alerts = check_thresholds(&stats, &thresholds);
+        assert_eq!((alerts[0].metric.as_str(), alerts[0].threshold), ("disk:/data", 95.0));
+    }
+
+    #[test]
+    fn the_alert_log_appends_one_json_line_per_alert() {
+        let path = std::env::temp_dir().join(format!("remote_management_alerts_{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let cpu = Alert { metric: "cpu".to_string(), value: 95.5, threshold: 90.0 };
+        AlertLog::open(&path).unwrap().record("web1", &cpu).unwrap();
+        // Reopening appends rather than truncating
+        AlertLog::open(&path).unwrap().record("web2", &Alert { metric: "disk:/data".to_string(), ..cpu }).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let entries: Vec<serde_json::Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(entries.len(), 2, "{}", text);
+        assert_eq!((&entries[0]["host"], &entries[0]["metric"]), (&serde_json::json!("web1"), &serde_json::json!("cpu")));
+        assert_eq!((entries[0]["value"].as_f64(), entries[0]["threshold"].as_f64()), (Some(95.5), Some(90.0)));
+        assert!(entries[0]["timestamp"].is_string());
+        assert_eq!(entries[1]["metric"], "disk:/data");
+    }
+}
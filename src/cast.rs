@@ -0,0 +1,136 @@
+// Recording the dashboard to an asciinema (v2) `.cast` file for `monitor --record`: a JSON
+// header line, then one `[seconds, "o", text]` line for each frame written to the terminal.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+pub struct Cast<W: Write> {
+    out: W,
+    started: Instant,
+    size: (u16, u16), // columns, rows
+}
+
+impl Cast<BufWriter<File>> {
+    pub fn create(path: &Path, size: (u16, u16)) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create recording {}", path.display()))?;
+        Cast::new(BufWriter::new(file), size).with_context(|| format!("Failed to write recording {}", path.display()))
+    }
+}
+
+impl<W: Write> Cast<W> {
+    pub fn new(mut out: W, (width, height): (u16, u16)) -> io::Result<Self> {
+        let header = serde_json::json!({
+            "version": 2,
+            "width": width,
+            "height": height,
+            "timestamp": chrono::Utc::now().timestamp(),
+            "env": { "TERM": std::env::var("TERM").unwrap_or_else(|_| "xterm-256color".to_string()) },
+        });
+        writeln!(out, "{}", header)?;
+        out.flush()?;
+        Ok(Cast { out, started: Instant::now(), size: (width, height) })
+    }
+
+    fn event(&mut self, kind: &str, data: &str) -> io::Result<()> {
+        let event = serde_json::json!([self.started.elapsed().as_secs_f64(), kind, data]);
+        writeln!(self.out, "{}", event)
+    }
+
+    // Flushed right away, so a session that ends in a crash still leaves a playable file
+    pub fn output(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.event("o", &String::from_utf8_lossy(data))?;
+        self.out.flush()
+    }
+
+    pub fn resize(&mut self, size: (u16, u16)) -> io::Result<()> {
+        if size == self.size {
+            return Ok(());
+        }
+        self.size = size;
+        self.event("r", &format!("{}x{}", size.0, size.1))
+    }
+
+    #[cfg(test)]
+    fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Passes everything through to the terminal and, when recording, also saves it as one cast
+/// event per flush. The dashboard flushes once per drawn frame, so frames are never split
+/// (nor a multi-byte character cut in half).
+pub struct Recorder<W: Write, C: Write = BufWriter<File>> {
+    inner: W,
+    cast: Option<Cast<C>>,
+    frame: Vec<u8>,
+}
+
+impl<W: Write, C: Write> Recorder<W, C> {
+    pub fn new(inner: W, cast: Option<Cast<C>>) -> Self {
+        Recorder { inner, cast, frame: Vec::new() }
+    }
+}
+
+impl<W: Write, C: Write> Write for Recorder<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if self.cast.is_some() {
+            self.frame.extend_from_slice(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        if let Some(cast) = &mut self.cast {
+            // A resize shows up as the terminal's new size by the time the next frame is drawn
+            if let Ok(size) = crossterm::terminal::size() {
+                cast.resize(size)?;
+            }
+            cast.output(&self.frame)?;
+            self.frame.clear();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_recorded_as_one_event_per_flush() {
+        let cast = Cast::new(Vec::new(), (80, 24)).unwrap();
+        let mut recorder = Recorder::new(Vec::new(), Some(cast));
+        write!(recorder, "\x1b[1;1H").unwrap();
+        write!(recorder, "CPU 42% ▇").unwrap();
+        recorder.flush().unwrap();
+        recorder.flush().unwrap(); // nothing new, so no empty event
+        write!(recorder, "CPU 43%").unwrap();
+        recorder.flush().unwrap();
+
+        assert_eq!(recorder.inner, "\x1b[1;1HCPU 42% ▇CPU 43%".as_bytes());
+        let cast = String::from_utf8(recorder.cast.unwrap().into_inner()).unwrap();
+        let mut lines = cast.lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap());
+        let header = lines.next().unwrap();
+        assert_eq!((header["version"].as_u64(), header["width"].as_u64(), header["height"].as_u64()), (Some(2), Some(80), Some(24)));
+        // The test's own terminal size may show up as a resize event
+        let output: Vec<_> = lines.filter(|event| event[1] == "o").map(|event| event[2].as_str().unwrap().to_string()).collect();
+        assert_eq!(output, ["\x1b[1;1HCPU 42% ▇", "CPU 43%"]);
+    }
+
+    #[test]
+    fn nothing_is_kept_without_a_recording() {
+        let mut recorder: Recorder<Vec<u8>, Vec<u8>> = Recorder::new(Vec::new(), None);
+        write!(recorder, "frame").unwrap();
+        recorder.flush().unwrap();
+        assert_eq!(recorder.inner, b"frame");
+        assert!(recorder.frame.is_empty());
+    }
+}
@@ -0,0 +1,1327 @@
+use crate::alerts::{health_check, AlertLog, CheckStatus, Thresholds};
+use crate::config::{load_config, split_destination};
+use crate::db::MetricsDb;
+use crate::fleet::{for_each_host, read_hosts_file};
+use crate::pushgateway::{prometheus_metrics, push_metrics, unreachable_metrics};
+use crate::report::{
+    copy_or_print, disk_usage_json, get_cron_report, get_disk_table, get_du_table, get_log_sources_report, get_exec_report, get_fleet_table, get_history_report, get_login_report, get_process_table, get_server_status, get_updates_table, journal_command, pending_updates, serialize_report, sort_fleet, start_spinner,
+    redact, warn, warn_degraded, warned, HostStatus, JournalWriter, RedactWriter, Redaction, Snapshot, WithSpinner, REDACTION, SHOW_WARNINGS, TABLE_STYLE, UTC_TIMESTAMPS,
+};
+use crate::ssh::{connect_and_auth, env_assignments, exec_command, reboot_host, run_script, use_agent_socket, watch_host, CommandOutput, Remote, RemoteExec, Target, VERBOSE};
+use crate::stats::{gather_stats, DEFAULT_HISTORY};
+use crate::tui::{chart_marker, monitor_fleet, monitor_plain, monitor_system, replay_samples, watch_command, DashboardView, MonitorOptions};
+use anyhow::{Context, Result};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use std::fs::File;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// `-V` shows the version and commit; `--version` adds the compiled-in features, for bug reports
+const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (", env!("BUILD_GIT_COMMIT"), ")");
+const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("BUILD_GIT_COMMIT"),
+    ")\nfeatures: ",
+    env!("BUILD_FEATURES")
+);
+
+#[derive(Parser)]
+#[command(name = "remote_management", version = VERSION, long_version = LONG_VERSION)]
+#[command(about = "A CLI tool for remote server management")]
+pub struct Cli {
+    /// Path to a config file (defaults to ~/.config/remote_management/config.toml)
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Print nothing on success, only errors; the exit status reports the result
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+    /// Log how long each remote command takes on stderr, to find what makes a report slow, and
+    /// which --identity key authenticated
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+    /// Border style for table output
+    #[arg(long, value_enum, global = true, default_value = "default")]
+    pub table_style: TableStyle,
+    /// Print timestamps as RFC 3339 in UTC instead of local time, for correlating across systems
+    #[arg(long, global = true)]
+    pub utc: bool,
+    /// SSH agent socket to authenticate with, when $SSH_AUTH_SOCK is wrong or unset (e.g. inside tmux)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub agent_socket: Option<PathBuf>,
+    /// Replace host names in output with placeholders (host-1, host-2, ...), for sharing reports and screenshots
+    #[arg(long, global = true)]
+    pub redact: bool,
+    /// With --redact, also replace IP addresses in output, including command output, with [ip]
+    #[arg(long, global = true, requires = "redact")]
+    pub redact_ips: bool,
+    /// Exit non-zero if anything was warned about (a metric no command could read, a stale mount,
+    /// ...), even when every threshold passes, for CI gates that should catch degraded hosts
+    #[arg(long, global = true)]
+    pub fail_on_warning: bool,
+    #[command(flatten)]
+    pub connection: ConnectionFlags,
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+// Connection settings that apply to every host a command talks to
+#[derive(Args, Default)]
+pub struct ConnectionFlags {
+    /// Run remote commands through this shell (e.g. /bin/bash) instead of the login shell
+    #[arg(long, global = true)]
+    pub shell: Option<String>,
+    /// Put this in front of every remote command, e.g. `docker exec web1` or `nsenter -t 1234 -a`
+    #[arg(long, global = true, value_name = "COMMAND")]
+    pub command_prefix: Option<String>,
+    /// known_hosts file to verify host keys against (defaults to ~/.ssh/known_hosts)
+    #[arg(long, global = true)]
+    pub known_hosts: Option<PathBuf>,
+    /// Connect through this command's stdin/stdout instead of TCP, like ssh's ProxyCommand (%h = host, %p = port)
+    #[arg(long, global = true)]
+    pub proxy_command: Option<String>,
+    /// Tunnel the SSH connection through this HTTP proxy with CONNECT (http://[user:pass@]proxy:port)
+    #[arg(long, global = true, value_name = "URL", conflicts_with = "proxy_command")]
+    pub proxy: Option<String>,
+    /// Carry the SSH connection over this Unix domain socket instead of TCP, e.g. a forwarded or sidecar socket
+    #[arg(long, global = true, value_name = "PATH", conflicts_with_all = ["proxy_command", "proxy"])]
+    pub unix_socket: Option<PathBuf>,
+    /// Seconds to allow for connecting, the SSH handshake and authentication (default: no limit)
+    #[arg(long, global = true, value_name = "SECS")]
+    pub connect_timeout: Option<u64>,
+    /// Seconds to allow for the SSH handshake alone, for hosts slow to send their banner (default: the connect timeout)
+    #[arg(long, global = true, value_name = "SECS")]
+    pub banner_timeout: Option<u64>,
+    /// Seconds to wait on a remote command's output before giving up on it, so a hung `df` can't
+    /// freeze a report (default: no limit). Streamed `exec` output is exempt
+    #[arg(long, global = true, value_name = "SECS")]
+    pub command_timeout: Option<u64>,
+    /// Refuse to run commands that change the remote host (reboot, kill, sudo, service restarts, ...)
+    #[arg(long, global = true)]
+    pub read_only: bool,
+    /// Ciphers to offer, comma-separated in order of preference (e.g. aes128-cbc,3des-cbc for old appliances)
+    #[arg(long, global = true, value_name = "LIST")]
+    pub cipher: Option<String>,
+    /// Key exchange methods to offer, comma-separated in order of preference (e.g. diffie-hellman-group1-sha1)
+    #[arg(long, global = true, value_name = "LIST")]
+    pub kex: Option<String>,
+    /// Ask for zlib compression of the SSH session, for slow links; costs CPU on fast ones
+    #[arg(long, global = true)]
+    pub compress: bool,
+    /// Ask for a password once and use it for every host that needs one, for fleets sharing credentials
+    #[arg(long, global = true)]
+    pub prompt_once: bool,
+    /// Private key to authenticate with; repeat to try several in order, before any password. The
+    /// SSH agent's other keys are then left out, like ssh's IdentitiesOnly
+    #[arg(long, global = true, value_name = "PATH")]
+    pub identity: Vec<PathBuf>,
+}
+
+#[derive(Subcommand)]
+pub enum Commands {
+    Status {
+        #[arg(short = 'H', long, required_unless_present_any = ["hosts_file", "destination"])]
+        host: Option<String>,
+        /// [USER@]HOST[:PORT] like ssh takes it, instead of -H, -u and -P
+        #[arg(conflicts_with_all = ["host", "hosts_file"])]
+        destination: Option<String>,
+        #[arg(short, long)]
+        username: Option<String>,
+        #[arg(short = 'P', long)]
+        port: Option<u16>,
+        /// File with one host per line; prints a combined summary for all of them
+        #[arg(long, conflicts_with = "host")]
+        hosts_file: Option<PathBuf>,
+        /// Maximum number of simultaneous connections when using --hosts-file
+        #[arg(long, default_value = "16")]
+        concurrency: usize,
+        /// Order of the --hosts-file summary (default: as listed); by a metric, unreachable hosts come first
+        #[arg(long, value_enum, conflicts_with_all = ["host", "destination"])]
+        sort_hosts: Option<HostSort>,
+        /// Print the unparsed stdout/stderr of each metric command instead of the table
+        #[arg(long, conflicts_with = "hosts_file")]
+        raw: bool,
+        /// Include extended sections such as recent and failed logins
+        #[arg(long, conflicts_with = "hosts_file")]
+        all: bool,
+        #[arg(long, value_enum, default_value = "table")]
+        format: StatusFormat,
+        /// Copy the report to the clipboard instead of printing it
+        #[arg(long)]
+        copy: bool,
+        /// Flag hosts that booted less than this many minutes ago
+        #[arg(long, value_name = "MINUTES")]
+        min_uptime: Option<u64>,
+        /// Show what changed since a snapshot saved earlier with `--format json`
+        #[arg(long, value_name = "SNAPSHOT", conflicts_with_all = ["hosts_file", "raw"])]
+        compare_to: Option<PathBuf>,
+        /// Push the stats to this Prometheus Pushgateway (http://host:9091) instead of printing a report
+        #[arg(long, value_name = "URL", conflicts_with_all = ["raw", "all", "format", "copy", "compare_to", "sort_hosts"])]
+        push_gateway: Option<String>,
+        /// Job name to push under; each host is a group of its own within it
+        #[arg(long, value_name = "NAME", default_value = "remote_management", requires = "push_gateway")]
+        job: String,
+    },
+    Monitor {
+        /// Host to monitor; falls back to REMOTE_MGMT_HOST when no host, destination or --hosts-file is given
+        #[arg(short = 'H', long)]
+        host: Option<String>,
+        /// [USER@]HOST[:PORT] like ssh takes it, instead of -H, -u and -P
+        #[arg(conflicts_with_all = ["host", "hosts_file"])]
+        destination: Option<String>,
+        #[arg(short, long, env = "REMOTE_MGMT_USER")]
+        username: Option<String>,
+        #[arg(short = 'P', long, env = "REMOTE_MGMT_PORT")]
+        port: Option<u16>,
+        /// File with one host per line; shows a health grid of all of them
+        #[arg(long, conflicts_with = "host")]
+        hosts_file: Option<PathBuf>,
+        /// Maximum number of simultaneous connection attempts when using --hosts-file
+        #[arg(long, default_value = "16")]
+        concurrency: usize,
+        #[arg(short = 'i', long, env = "REMOTE_MGMT_INTERVAL", default_value = "1")]
+        interval: u64,
+        /// CPU usage percentage that triggers an alert
+        #[arg(long, env = "REMOTE_MGMT_CPU_ALERT", default_value = "90")]
+        cpu_alert: f64,
+        /// Memory usage percentage that triggers an alert
+        #[arg(long, env = "REMOTE_MGMT_MEM_ALERT", default_value = "90")]
+        mem_alert: f64,
+        /// Disk usage percentage (per mount) that triggers an alert, unless `disk_thresholds` in the config has a rule for the mount
+        #[arg(long, env = "REMOTE_MGMT_DISK_ALERT", default_value = "90")]
+        disk_alert: f64,
+        /// Webhook URL (Slack, Discord or generic) to POST alerts to as JSON
+        #[arg(long, conflicts_with = "hosts_file")]
+        alert_webhook: Option<String>,
+        /// Don't fire an alert for a metric that already fired this many minutes ago or less, when
+        /// it drops below its threshold and crosses it again
+        #[arg(long, value_name = "MINUTES", conflicts_with = "hosts_file")]
+        alert_cooldown: Option<u64>,
+        /// File to append every fired alert to, one JSON object per line
+        #[arg(long, value_name = "PATH", conflicts_with = "hosts_file")]
+        alert_log: Option<PathBuf>,
+        /// Flag hosts that booted less than this many minutes ago
+        #[arg(long, value_name = "MINUTES")]
+        min_uptime: Option<u64>,
+        /// Number of samples kept for the CPU and load charts (default 100, or `history` in the config)
+        #[arg(long, value_name = "N")]
+        history: Option<usize>,
+        /// SQLite database to store every sample in, for the `history` command (needs the `sqlite` feature)
+        #[arg(long, value_name = "PATH", conflicts_with = "hosts_file")]
+        db: Option<PathBuf>,
+        /// How charts draw their lines; use dot or block if the graph shows boxes or question marks
+        #[arg(long, value_enum, default_value = "auto")]
+        marker: ChartMarker,
+        /// Show a key for the green/yellow/red usage colors
+        #[arg(long)]
+        color_scale: bool,
+        /// Whether memory and disks start out shown as percentages or sizes (toggle with `a`)
+        #[arg(long, value_enum, default_value = "pct")]
+        units: Units,
+        /// Show CPU and load as gauges instead of history charts (automatic on short terminals)
+        #[arg(long)]
+        compact: bool,
+        /// Print each sample as plain text instead of drawing the dashboard, for dumb terminals and CI logs
+        #[arg(long, conflicts_with = "hosts_file")]
+        no_tui: bool,
+        /// Also sample context switches, interrupts and the run queue, shown in a Kernel panel
+        #[arg(long, conflicts_with = "hosts_file")]
+        include_kernel_metrics: bool,
+        /// Times to retry a metric command whose channel fails before giving up
+        #[arg(long, default_value = "2", conflicts_with = "hosts_file")]
+        retries: u32,
+        /// TOML file of dashboard colors, reloaded while monitoring whenever it changes
+        #[arg(long, value_name = "PATH")]
+        theme_from_file: Option<PathBuf>,
+        /// Only list the N fullest mounts, for hosts with more than fit on screen
+        #[arg(long, value_name = "N")]
+        max_disks: Option<usize>,
+        /// Save the dashboard as an asciinema recording (.cast) while monitoring
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["hosts_file", "no_tui"])]
+        record: Option<PathBuf>,
+    },
+    /// Show samples that `monitor --db` stored for a host
+    History {
+        #[arg(short = 'H', long)]
+        host: String,
+        /// Database written by `monitor --db`
+        #[arg(long, value_name = "PATH")]
+        db: PathBuf,
+        /// Only include samples from the last this many hours
+        #[arg(long, value_name = "HOURS")]
+        since: Option<u64>,
+        /// Print the minimum, average and maximum of each metric instead of every sample
+        #[arg(long)]
+        summary: bool,
+        #[arg(long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+    /// Play samples stored by `monitor --db` back through the dashboard, to review an incident after the fact
+    Replay {
+        /// Database written by `monitor --db`
+        #[arg(value_name = "DB")]
+        db: PathBuf,
+        #[arg(short = 'H', long)]
+        host: String,
+        /// Only include samples from the last this many hours
+        #[arg(long, value_name = "HOURS")]
+        since: Option<u64>,
+        /// Recorded seconds to play per second (change with +/- while playing)
+        #[arg(long, default_value = "10")]
+        speed: f64,
+        /// Number of samples the charts show (default 100, or `history` in the config)
+        #[arg(long, value_name = "N")]
+        history: Option<usize>,
+        /// How charts draw their lines; use dot or block if the graph shows boxes or question marks
+        #[arg(long, value_enum, default_value = "auto")]
+        marker: ChartMarker,
+    },
+    /// Show recent successful and failed logins
+    Logins {
+        #[command(flatten)]
+        target: HostArgs,
+    },
+    /// List the cron jobs in the system crontabs, /etc/cron.d, the run-parts directories and each user's crontab
+    Cron {
+        #[command(flatten)]
+        target: HostArgs,
+        #[arg(long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+    /// Run a command on the remote host and stream its output, or on every host in a file
+    Exec {
+        #[arg(short = 'H', long, required_unless_present_any = ["hosts_file", "destination"])]
+        host: Option<String>,
+        /// [USER@]HOST[:PORT] like ssh takes it, instead of -H, -u and -P
+        #[arg(conflicts_with_all = ["host", "hosts_file"])]
+        destination: Option<String>,
+        #[arg(short, long)]
+        username: Option<String>,
+        #[arg(short = 'P', long)]
+        port: Option<u16>,
+        /// File with one host per line; runs the command on all of them and prints the output grouped by host
+        #[arg(long, conflicts_with = "host")]
+        hosts_file: Option<PathBuf>,
+        /// Maximum number of simultaneous connections when using --hosts-file
+        #[arg(long, default_value = "16")]
+        concurrency: usize,
+        /// Don't start the command on any more hosts once one has failed
+        #[arg(long, conflicts_with_all = ["host", "destination"])]
+        fail_fast: bool,
+        /// How to print the grouped results of --hosts-file (default text)
+        #[arg(long, value_enum, conflicts_with_all = ["host", "destination"])]
+        format: Option<ExecFormat>,
+        /// Environment variable for the remote command (repeatable)
+        #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_var)]
+        env: Vec<(String, String)>,
+        /// Local file to send as the command's stdin; `-` passes our own stdin through
+        #[arg(long, value_name = "PATH", conflicts_with = "hosts_file")]
+        stdin_file: Option<PathBuf>,
+        /// Command to run, after `--`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Keep one connection open and run commands typed at a prompt, each over a fresh channel, until `exit`
+    Repl {
+        #[command(flatten)]
+        target: HostArgs,
+    },
+    /// Run a command repeatedly and show its latest output full-screen, like watch(1)
+    Watch {
+        #[command(flatten)]
+        target: HostArgs,
+        /// Seconds between runs
+        #[arg(short = 'n', long, default_value = "2")]
+        interval: u64,
+        /// Highlight lines that changed since the previous run
+        #[arg(short, long)]
+        diff: bool,
+        /// Command to run, after `--`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// List processes from `ps aux`, optionally filtered and sorted
+    Processes {
+        #[command(flatten)]
+        target: HostArgs,
+        /// Only show processes whose command line contains this text
+        #[arg(long)]
+        filter: Option<String>,
+        /// Only show processes owned by this user
+        #[arg(long = "user")]
+        owner: Option<String>,
+        #[arg(long, value_enum, default_value = "cpu")]
+        sort: ProcessSort,
+        /// Only show the first N processes in the sort order
+        #[arg(long, value_name = "N")]
+        max_procs: Option<usize>,
+        #[arg(long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+    /// Show recent systemd journal entries, colored by priority
+    Journal {
+        #[command(flatten)]
+        target: HostArgs,
+        /// Lowest priority to show (emerg, alert, crit, err, warning, notice, info, debug)
+        #[arg(short, long, default_value = "err")]
+        priority: String,
+        /// Only show entries newer than this, in any form journalctl accepts (e.g. "1 hour ago")
+        #[arg(long)]
+        since: Option<String>,
+        /// Only show entries from this systemd unit
+        #[arg(long)]
+        unit: Option<String>,
+        /// Show at most this many of the most recent entries
+        #[arg(short = 'n', long)]
+        lines: Option<usize>,
+    },
+    /// Show usage of every mounted filesystem
+    Disks {
+        #[command(flatten)]
+        target: HostArgs,
+        #[arg(long, value_enum, default_value = "table")]
+        format: OutputFormat,
+        /// Print the mounts as a JSON array with byte counts, for scripts
+        #[arg(long, conflicts_with = "format")]
+        json: bool,
+        /// Only list the N fullest mounts (in the usual order), for hosts with hundreds of them
+        #[arg(long, value_name = "N", conflicts_with = "json")]
+        max_disks: Option<usize>,
+    },
+    /// Show which directories under a path use the most space, like `du | sort -rh | head`
+    Du {
+        #[command(flatten)]
+        target: HostArgs,
+        /// Directory to look in; du doesn't cross into other filesystems mounted below it
+        #[arg(default_value = "/")]
+        path: String,
+        /// How many levels of subdirectories to list
+        #[arg(long, default_value = "1")]
+        depth: usize,
+        /// Show this many of the largest directories
+        #[arg(long, default_value = "10")]
+        top: usize,
+        #[arg(long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+    /// Show which files in /var/log and which journald units log the most, for a disk filling up
+    LogSources {
+        #[command(flatten)]
+        target: HostArgs,
+        /// How far back to count journal entries per unit, in any form journalctl accepts
+        #[arg(long, default_value = "24 hours ago")]
+        since: String,
+        /// Show this many of the largest files and busiest units
+        #[arg(long, default_value = "10")]
+        top: usize,
+        #[arg(long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+    /// Count pending package updates (apt, dnf or yum) and how many of them fix security issues
+    Updates {
+        #[arg(short = 'H', long, required_unless_present_any = ["hosts_file", "destination"])]
+        host: Option<String>,
+        /// [USER@]HOST[:PORT] like ssh takes it, instead of -H, -u and -P
+        #[arg(conflicts_with_all = ["host", "hosts_file"])]
+        destination: Option<String>,
+        #[arg(short, long)]
+        username: Option<String>,
+        #[arg(short = 'P', long)]
+        port: Option<u16>,
+        /// File with one host per line; hosts with pending security updates are highlighted
+        #[arg(long, conflicts_with = "host")]
+        hosts_file: Option<PathBuf>,
+        /// Maximum number of simultaneous connections when using --hosts-file
+        #[arg(long, default_value = "16")]
+        concurrency: usize,
+        #[arg(long, value_enum, default_value = "table")]
+        format: OutputFormat,
+    },
+    /// Check a host against thresholds and print the result as JSON; exits 0 (ok), 2 (critical) or 3 (unknown)
+    Check {
+        #[command(flatten)]
+        target: HostArgs,
+        /// CPU usage percentage at or above which the check fails
+        #[arg(long, default_value = "90")]
+        cpu: f64,
+        /// Memory usage percentage at or above which the check fails
+        #[arg(long, default_value = "90")]
+        mem: f64,
+        /// Disk usage percentage (per mount) at or above which the check fails, unless `disk_thresholds` in the config has a rule for the mount
+        #[arg(long, default_value = "90")]
+        disk: f64,
+        #[arg(long, value_enum, default_value = "json")]
+        format: DataFormat,
+    },
+    /// Upload a local script, run it on the host and report pass or fail; exits 0 (pass), 2 (fail) or 3 (couldn't run)
+    Healthcheck {
+        #[command(flatten)]
+        target: HostArgs,
+        /// Local script to run; it's copied to a temporary file on the host and removed afterwards
+        #[arg(long)]
+        script: PathBuf,
+        /// Arguments for the script, after `--`
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Probe a host's SSH port on an interval and log when it goes down and comes back, until Ctrl-C
+    WatchHost {
+        #[command(flatten)]
+        target: HostArgs,
+        /// Seconds between probes
+        #[arg(short = 'n', long, default_value = "5")]
+        interval: u64,
+        /// Seconds a probe may take to connect and finish the SSH handshake before the host counts as down
+        #[arg(long, default_value = "5")]
+        timeout: u64,
+    },
+    /// Reboot the remote host
+    Reboot {
+        #[command(flatten)]
+        target: HostArgs,
+        /// Wait for the host to go down and accept SSH handshakes again, reporting the downtime
+        #[arg(long)]
+        wait: bool,
+        /// Seconds to wait for the host to come back with --wait
+        #[arg(long, default_value = "600")]
+        wait_timeout: u64,
+    },
+    /// Print a shell completion script, e.g. `remote_management completions bash > /etc/bash_completion.d/remote_management`
+    #[command(hide = true)]
+    Completions {
+        // Its own id, as `shell` is taken by the global --shell
+        #[arg(id = "completion_shell", value_enum, value_name = "SHELL")]
+        shell: clap_complete::Shell,
+    },
+}
+
+pub fn parse_env_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got `{}`", s)),
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    /// Two-column tables as full-width sections, one per row, for long command output
+    TableWide,
+    /// GitHub-flavored Markdown tables
+    Markdown,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum StatusFormat {
+    Table,
+    /// Each metric in its own full-width section
+    TableWide,
+    Markdown,
+    /// The parsed stats, for scripts
+    Json,
+    Yaml,
+}
+
+impl StatusFormat {
+    fn data(self) -> Option<DataFormat> {
+        match self {
+            StatusFormat::Table | StatusFormat::TableWide | StatusFormat::Markdown => None,
+            StatusFormat::Json => Some(DataFormat::Json),
+            StatusFormat::Yaml => Some(DataFormat::Yaml),
+        }
+    }
+
+    fn table(self) -> OutputFormat {
+        match self {
+            StatusFormat::Markdown => OutputFormat::Markdown,
+            StatusFormat::TableWide => OutputFormat::TableWide,
+            _ => OutputFormat::Table,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum DataFormat {
+    Json,
+    Yaml,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExecFormat {
+    Text,
+    /// An array with each host's exit status, stdout, stderr and error
+    Json,
+}
+
+// Where a subcommand connects, before config resolution
+struct Destinations<'a> {
+    hosts: Hosts<'a>,
+    username: Option<&'a String>,
+    port: Option<u16>,
+}
+
+enum Hosts<'a> {
+    One(&'a str),
+    File(&'a Path),
+}
+
+impl Commands {
+    // What a subcommand would change on the remote hosts, if anything. Deliberately has no
+    // catch-all arm, so every new subcommand has to decide whether read-only mode blocks it.
+    fn mutation(&self) -> Option<(Destinations<'_>, String)> {
+        let run = |command: &[String]| mutating_command(&command.join(" ")).map(|word| format!("run `{}`", word));
+        match self {
+            Commands::Reboot { target, .. } => Some((target.destinations(), "reboot".to_string())),
+            Commands::Exec { host, destination, username, port, hosts_file, command, .. } => {
+                let hosts = match (hosts_file, host.as_ref().or(destination.as_ref())) {
+                    (Some(path), _) => Hosts::File(path),
+                    (None, host) => Hosts::One(host.expect("clap requires a host without --hosts-file")),
+                };
+                run(command).map(|action| (Destinations { hosts, username: username.as_ref(), port: *port }, action))
+            }
+            Commands::Watch { target, command, .. } => run(command).map(|action| (target.destinations(), action)),
+            // An unreadable script isn't a mutation; uploading it reports the error instead
+            Commands::Healthcheck { target, script, .. } => std::fs::read_to_string(script)
+                .ok()
+                .and_then(|contents| script_mutation(&contents).map(|word| format!("run a script that calls `{}`", word)))
+                .map(|action| (target.destinations(), action)),
+            // Each line typed at the prompt is checked as it's entered instead
+            Commands::Repl { .. } => None,
+            Commands::Status { .. }
+            | Commands::Monitor { .. }
+            | Commands::Logins { .. }
+            | Commands::Cron { .. }
+            | Commands::Check { .. }
+            | Commands::Disks { .. }
+            | Commands::Du { .. }
+            | Commands::LogSources { .. }
+            | Commands::WatchHost { .. }
+            | Commands::Updates { .. }
+            | Commands::History { .. }
+            | Commands::Replay { .. }
+            | Commands::Processes { .. }
+            | Commands::Journal { .. }
+            | Commands::Completions { .. } => None,
+        }
+    }
+}
+
+// Finds a word in a shell command line that changes system state. This is a guardrail against
+// mistakes, not a sandbox: it can't see through scripts or aliases.
+fn mutating_command(command: &str) -> Option<&str> {
+    const ALWAYS: [&str; 16] = [
+        "reboot", "shutdown", "halt", "poweroff", "init", "kill", "killall", "pkill", "sudo", "su", "doas", "service",
+        "rm", "dd", "mkfs", "scp",
+    ];
+    const SYSTEMCTL_ACTIONS: [&str; 10] =
+        ["start", "stop", "restart", "reload", "enable", "disable", "mask", "kill", "reboot", "poweroff"];
+
+    let words: Vec<&str> = command
+        .split(|c: char| c.is_whitespace() || ";|&()`".contains(c))
+        .filter(|w| !w.is_empty())
+        .collect();
+    for (i, word) in words.iter().enumerate() {
+        // Match /sbin/reboot as well as reboot
+        let name = word.rsplit('/').next().unwrap_or(word);
+        if ALWAYS.contains(&name) {
+            return Some(word);
+        }
+        if name == "systemctl" && words.get(i + 1).is_some_and(|action| SYSTEMCTL_ACTIONS.contains(action)) {
+            return Some(word);
+        }
+    }
+    None
+}
+
+// The first mutating command on a script's non-comment lines
+fn script_mutation(script: &str) -> Option<&str> {
+    script.lines().map(str::trim).filter(|line| !line.starts_with('#')).find_map(mutating_command)
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum TableStyle {
+    Default,
+    /// No separators between rows
+    Compact,
+    /// No borders or separators at all
+    Clean,
+    /// Separators between rows but no outer border
+    Borderless,
+    /// Box-drawing characters
+    Box,
+    /// Same as --format markdown
+    Markdown,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ChartMarker {
+    /// Braille unless the terminal looks like it can't show it
+    Auto,
+    /// Finest lines; needs a font with Unicode Braille patterns
+    Braille,
+    Dot,
+    Block,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Units {
+    #[value(name = "pct")]
+    Percent,
+    /// Used and total sizes, e.g. 1.9 GiB
+    #[value(name = "abs")]
+    Absolute,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ProcessSort {
+    Cpu,
+    Mem,
+    Pid,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HostSort {
+    /// Highest CPU usage first
+    #[value(name = "by-cpu")]
+    Cpu,
+    /// Highest memory usage first
+    #[value(name = "by-mem")]
+    Mem,
+    /// Fullest disk first
+    #[value(name = "by-disk")]
+    Disk,
+    #[value(name = "by-name")]
+    Name,
+}
+
+#[derive(Args)]
+pub struct HostArgs {
+    #[arg(short = 'H', long = "host", value_name = "HOST", required_unless_present = "destination")]
+    host_flag: Option<String>,
+    /// [USER@]HOST[:PORT] like ssh takes it, instead of -H, -u and -P
+    #[arg(conflicts_with = "host_flag")]
+    destination: Option<String>,
+    #[arg(short, long)]
+    pub username: Option<String>,
+    #[arg(short = 'P', long)]
+    pub port: Option<u16>,
+}
+
+impl HostArgs {
+    pub fn host(&self) -> &str {
+        self.host_flag.as_deref().or(self.destination.as_deref()).expect("clap requires a host")
+    }
+
+    fn destinations(&self) -> Destinations<'_> {
+        Destinations { hosts: Hosts::One(self.host()), username: self.username.as_ref(), port: self.port }
+    }
+}
+
+pub async fn run() -> Result<()> {
+    let cli = Cli::parse();
+    let fail_on_warning = cli.fail_on_warning;
+    let result = run_cli(cli).await.and_then(|()| match fail_on_warning && warned() {
+        true => Err(anyhow::anyhow!("Failing because of the warnings above (--fail-on-warning)")),
+        false => Ok(()),
+    });
+    // Errors name hosts too, and main prints them after we're done
+    match REDACTION.get() {
+        Some(redaction) => result.map_err(|e| anyhow::anyhow!(redaction.apply(&format!("{:#}", e)))),
+        None => result,
+    }
+}
+
+async fn run_cli(cli: Cli) -> Result<()> {
+    // Generated from the same definitions as the parser, so the scripts can't fall behind it. Done
+    // before the config is read, so a broken config doesn't break tab completion too.
+    if let Commands::Completions { shell } = cli.command {
+        // Written out here rather than by `generate`, which panics if the pipe is closed
+        let mut script = Vec::new();
+        clap_complete::generate(shell, &mut Cli::command(), "remote_management", &mut script);
+        std::io::stdout().write_all(&script)?;
+        return Ok(());
+    }
+    let Cli { config, quiet, verbose, table_style, utc, agent_socket, redact: redact_hosts, redact_ips, fail_on_warning, connection, command } = cli;
+    let _ = TABLE_STYLE.set(table_style);
+    let _ = SHOW_WARNINGS.set(!quiet || fail_on_warning);
+    let _ = VERBOSE.set(verbose);
+    if redact_hosts {
+        let _ = REDACTION.set(Redaction::new(redact_ips));
+    }
+    let config = load_config(config.as_deref())?;
+    let _ = UTC_TIMESTAMPS.set(utc || config.utc.unwrap_or(false));
+    if let Some(socket) = agent_socket.or_else(|| config.agent_socket.clone()) {
+        use_agent_socket(&socket)?;
+    }
+    let resolve = |host: &str, username: Option<String>, port: Option<u16>| {
+        let target = config.resolve(host, username, port, &connection);
+        if let Some(redaction) = REDACTION.get() {
+            redaction.add_host(&[host, split_destination(host).1, &target.host]);
+        }
+        target
+    };
+    let resolve_args = |args: &HostArgs| resolve(args.host(), args.username.clone(), args.port);
+
+    if let Some((destinations, action)) = command.mutation() {
+        let hosts = match destinations.hosts {
+            Hosts::One(host) => vec![host.to_string()],
+            Hosts::File(path) => read_hosts_file(path)?,
+        };
+        for host in hosts {
+            let target = resolve(&host, destinations.username.cloned(), destinations.port);
+            if target.read_only {
+                anyhow::bail!(
+                    "Refusing to {} on {}: read-only mode is on (--read-only or read_only in the config)",
+                    action,
+                    target.host
+                );
+            }
+        }
+    }
+
+    match command {
+        Commands::Status {
+            host,
+            destination,
+            username,
+            port,
+            hosts_file,
+            concurrency,
+            sort_hosts,
+            raw,
+            all,
+            format,
+            copy,
+            min_uptime,
+            compare_to,
+            push_gateway,
+            job,
+        } => {
+            if let Some(gateway) = push_gateway {
+                let hosts = match hosts_file {
+                    Some(hosts_file) => read_hosts_file(&hosts_file)?,
+                    None => vec![host.or(destination).expect("clap requires a host without --hosts-file")],
+                };
+                let targets = hosts
+                    .into_iter()
+                    .map(|host| {
+                        let target = resolve(&host, username.clone(), port);
+                        (host, target)
+                    })
+                    .collect();
+                let results = for_each_host(targets, concurrency, |target| Ok(gather_stats(&connect_and_auth(target)?)?)).await?;
+                let client = reqwest::Client::new();
+                let mut failed = 0;
+                for (host, result) in &results {
+                    // An unreachable host is pushed as down, so its last good figures don't linger
+                    let metrics = match result {
+                        Ok(stats) => {
+                            warn_degraded(host, stats);
+                            prometheus_metrics(stats)
+                        }
+                        Err(_) => unreachable_metrics(),
+                    };
+                    let pushed = push_metrics(&client, &gateway, &job, host, metrics).await;
+                    match (result, &pushed) {
+                        (Ok(_), Ok(())) if !quiet => println!("{}", redact(&format!("{}: pushed to {}", host, gateway))),
+                        (Ok(_), Ok(())) => {}
+                        (Err(e), _) | (_, Err(e)) => {
+                            failed += 1;
+                            eprintln!("{}", redact(&format!("{}: Error: {:#}", host, e)));
+                        }
+                    }
+                }
+                if failed > 0 {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+            let min_uptime = min_uptime.map(|minutes| Duration::from_secs(minutes * 60));
+            let (data_format, format) = (format.data(), format.table());
+            if data_format.is_some() && (raw || all || compare_to.is_some()) {
+                anyhow::bail!("--raw, --all and --compare-to only apply to table and markdown output");
+            }
+            let baseline = compare_to.as_deref().map(Snapshot::load).transpose()?;
+            let spinner = start_spinner(!quiet);
+            if let Some(hosts_file) = hosts_file {
+                let targets: Vec<_> = read_hosts_file(&hosts_file)?
+                    .into_iter()
+                    .map(|host| {
+                        let target = resolve(&host, username.clone(), port);
+                        (host, target)
+                    })
+                    .collect();
+                let total = targets.len();
+                spinner.set_message(format!("Gathering stats from {} hosts", total));
+                let progress = spinner.clone();
+                let mut results = for_each_host(targets, concurrency, move |target| {
+                    let result = connect_and_auth(target).and_then(|remote| gather_stats(&remote));
+                    progress.inc(1);
+                    progress.set_message(format!("Gathering stats ({}/{} hosts done)", progress.position(), total));
+                    Ok(result?)
+                })
+                .await?;
+                spinner.finish_and_clear();
+                for (host, stats) in results.iter().filter_map(|(host, result)| Some((host, result.as_ref().ok()?))) {
+                    warn_degraded(host, stats);
+                }
+                if let Some(by) = sort_hosts {
+                    sort_fleet(&mut results, by);
+                }
+                let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+                let report = match data_format {
+                    Some(data_format) => {
+                        let hosts: Vec<HostStatus> = results.iter().map(HostStatus::from).collect();
+                        serialize_report(&hosts, data_format)?
+                    }
+                    None => get_fleet_table(&results, format, min_uptime),
+                };
+                // As for a single host, --copy still copies under --quiet
+                if copy {
+                    copy_or_print(&redact(&report), quiet);
+                } else if !quiet {
+                    println!("{}", redact(&report));
+                }
+                if quiet {
+                    for (host, result) in &results {
+                        if let Err(e) = result {
+                            eprintln!("{}", redact(&format!("{}: Error: {:#}", host, e)));
+                        }
+                    }
+                }
+                if failed > 0 {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+
+            let host = host.or(destination).expect("clap requires a host without --hosts-file");
+            let target = resolve(&host, username, port);
+            let status = match data_format {
+                Some(data_format) => {
+                    spinner.set_message(format!("Connecting to {}", target.host));
+                    connect_and_auth(&target)
+                        .and_then(|remote| gather_stats(&WithSpinner { inner: &remote, spinner: &spinner }))
+                        .map_err(anyhow::Error::from)
+                        .inspect(|stats| warn_degraded(&target.host, stats))
+                        .and_then(|stats| serialize_report(&HostStatus { host: &host, stats: Some(&stats), error: None }, data_format))
+                }
+                None => get_server_status(&target, raw, all, format, min_uptime, baseline.as_ref(), &spinner),
+            };
+            spinner.finish_and_clear();
+            match status {
+                Ok(status) if copy => copy_or_print(&redact(&status), quiet),
+                Ok(status) if !quiet => println!("{}", redact(&status)),
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Error: {}", redact(&format!("{:#}", e)));
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Monitor {
+            host,
+            destination,
+            username,
+            port,
+            hosts_file,
+            concurrency,
+            interval,
+            cpu_alert,
+            mem_alert,
+            disk_alert,
+            alert_webhook,
+            alert_cooldown,
+            alert_log,
+            min_uptime,
+            history,
+            db,
+            marker,
+            color_scale,
+            units,
+            compact,
+            no_tui,
+            include_kernel_metrics,
+            retries,
+            theme_from_file,
+            max_disks,
+            record,
+        } => {
+            let min_uptime = min_uptime.map(|minutes| Duration::from_secs(minutes * 60));
+            let history = history.or(config.history).unwrap_or(DEFAULT_HISTORY);
+            let thresholds = Thresholds {
+                cpu: cpu_alert,
+                memory: mem_alert,
+                disk: disk_alert,
+                disk_by_mount: config.disk_thresholds.clone().into_iter().collect(),
+            };
+            let marker = chart_marker(marker);
+            let theme_file = theme_from_file.or_else(|| config.theme_file.clone());
+
+            if let Some(hosts_file) = hosts_file {
+                // There's no plain-text grid, so say so before prompting for any passwords
+                if !std::io::stdout().is_terminal() {
+                    anyhow::bail!("monitor --hosts-file needs a terminal; use status --hosts-file for output to a file or pipe");
+                }
+                // Connect (and prompt for any passwords) before the TUI takes over the terminal
+                let targets = read_hosts_file(&hosts_file)?
+                    .into_iter()
+                    .map(|host| {
+                        let target = resolve(&host, username.clone(), port);
+                        (host, target)
+                    })
+                    .collect();
+                let remotes = for_each_host(targets, concurrency, |target| Ok(connect_and_auth(target)?)).await?;
+                let mut view = DashboardView::new(marker, color_scale, units, compact);
+                view.max_disks = max_disks;
+                monitor_fleet(remotes, interval, &thresholds, min_uptime, history, view, theme_file).await?;
+                return Ok(());
+            }
+
+            // Read here rather than through clap's `env`, so a host in the environment doesn't
+            // conflict with a destination or --hosts-file given on the command line
+            let host = host
+                .or(destination)
+                .or_else(|| std::env::var("REMOTE_MGMT_HOST").ok().filter(|host| !host.is_empty()))
+                .context("monitor needs a host: pass -H, a destination or --hosts-file, or set REMOTE_MGMT_HOST")?;
+            let target = resolve(&host, username, port);
+            let db = db.as_deref().map(MetricsDb::open).transpose()?;
+            let alert_log = alert_log.as_deref().map(AlertLog::open).transpose()?;
+            let mut sess = connect_and_auth(&target)?;
+            let options = MonitorOptions {
+                host: target.host.clone(),
+                target,
+                interval,
+                thresholds,
+                alert_webhook,
+                alert_cooldown: alert_cooldown.map(|minutes| Duration::from_secs(minutes * 60)),
+                alert_log,
+                min_uptime,
+                history,
+                db,
+                marker,
+                color_scale,
+                units,
+                compact,
+                retries,
+                theme_file,
+                kernel_metrics: include_kernel_metrics,
+                max_disks,
+                record,
+            };
+            let summary = if no_tui {
+                monitor_plain(&mut sess, &options).await?
+            } else {
+                monitor_system(&mut sess, &options).await?
+            };
+            println!("{}", redact(&summary.to_string()));
+        }
+        Commands::History { host, db, since, summary, format } => {
+            // Samples are stored under the resolved host name, so profile names work here too
+            let host = resolve(&host, None, None).host;
+            let since = since.map_or(0, |hours| chrono::Utc::now().timestamp() - hours as i64 * 3600);
+            let samples = MetricsDb::open(&db)?.samples(&host, since)?;
+            println!("{}", redact(&get_history_report(&host, &samples, summary, format)));
+        }
+        Commands::Replay { db, host, since, speed, history, marker } => {
+            if speed.is_nan() || speed <= 0.0 {
+                anyhow::bail!("--speed must be more than 0");
+            }
+            let host = resolve(&host, None, None).host;
+            let since = since.map_or(0, |hours| chrono::Utc::now().timestamp() - hours as i64 * 3600);
+            let samples = MetricsDb::open(&db)?.samples(&host, since)?;
+            let history = history.or(config.history).unwrap_or(DEFAULT_HISTORY);
+            let view = DashboardView::new(chart_marker(marker), false, Units::Percent, false);
+            replay_samples(&redact(&host), &samples, speed, history, view)?;
+        }
+        Commands::Logins { target } => {
+            let target = resolve_args(&target);
+            let sess = connect_and_auth(&target)?;
+            println!("{}", redact(&get_login_report(&sess, OutputFormat::Table)?));
+        }
+        Commands::Cron { target, format } => {
+            let target = resolve_args(&target);
+            let sess = connect_and_auth(&target)?;
+            println!("{}", redact(&get_cron_report(&sess, format)?));
+        }
+        Commands::Exec {
+            host,
+            destination,
+            username,
+            port,
+            hosts_file,
+            concurrency,
+            fail_fast,
+            format,
+            env,
+            stdin_file,
+            command,
+        } => {
+            let command = command.join(" ");
+            if let Some(hosts_file) = hosts_file {
+                let targets = read_hosts_file(&hosts_file)?
+                    .into_iter()
+                    .map(|host| {
+                        let target = resolve(&host, username.clone(), port);
+                        (host, target)
+                    })
+                    .collect();
+                // Hosts already running finish either way; --fail-fast only stops new ones starting
+                let failed = Arc::new(AtomicBool::new(false));
+                let command = format!("{}{}", env_assignments(&env), command);
+                let results = for_each_host(targets, concurrency, {
+                    let failed = failed.clone();
+                    move |target| {
+                        if fail_fast && failed.load(Ordering::SeqCst) {
+                            anyhow::bail!("Skipped after an earlier host failed");
+                        }
+                        let result = connect_and_auth(target).and_then(|remote| remote.run_command_output(&command));
+                        if !matches!(result, Ok(CommandOutput { exit_status: 0, .. })) {
+                            failed.store(true, Ordering::SeqCst);
+                        }
+                        Ok(result?)
+                    }
+                })
+                .await?;
+                if !quiet {
+                    println!("{}", redact(&get_exec_report(&results, format.unwrap_or(ExecFormat::Text))));
+                }
+                if failed.load(Ordering::SeqCst) {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+
+            let host = host.or(destination).expect("clap requires a host without --hosts-file");
+            let target = resolve(&host, username, port);
+            // Open the file before connecting, so a typo doesn't cost a password prompt
+            let mut input: Option<Box<dyn Read>> = match stdin_file {
+                Some(path) if path.as_os_str() == "-" => Some(Box::new(std::io::stdin())),
+                Some(path) => Some(Box::new(
+                    File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?,
+                )),
+                None => None,
+            };
+            let sess = connect_and_auth(&target)?;
+            let input = input.as_deref_mut().map(|input| input as &mut dyn Read);
+            let mut out = RedactWriter { inner: std::io::stdout(), pending: Vec::new() };
+            let status = exec_command(&sess, &command, &env, input, &mut out)?;
+            out.flush()?;
+            if status != 0 {
+                std::process::exit(status);
+            }
+        }
+        Commands::Repl { target } => {
+            let target = resolve_args(&target);
+            let remote = connect_and_auth(&target)?;
+            repl(&remote, &target)?;
+        }
+        Commands::Watch { target, interval, diff, command } => {
+            let target = resolve_args(&target);
+            let remote = connect_and_auth(&target)?;
+            watch_command(&remote, &target.host, &command.join(" "), interval, diff).await?;
+        }
+        Commands::Processes { target, filter, owner, sort, max_procs, format } => {
+            let target = resolve_args(&target);
+            let sess = connect_and_auth(&target)?;
+            let table = get_process_table(&sess, filter.as_deref(), owner.as_deref(), sort, max_procs, format)?;
+            println!("{}", redact(&table));
+        }
+        Commands::Journal { target, priority, since, unit, lines } => {
+            let target = resolve_args(&target);
+            let sess = connect_and_auth(&target)?;
+            let command = journal_command(&priority, since.as_deref(), unit.as_deref(), lines);
+            let mut out = JournalWriter {
+                inner: RedactWriter { inner: std::io::stdout(), pending: Vec::new() },
+                pending: Vec::new(),
+                color: std::io::stdout().is_terminal(),
+            };
+            let status = exec_command(&sess, &command, &[], None, &mut out)?;
+            out.flush()?;
+            if status != 0 {
+                std::process::exit(status);
+            }
+        }
+        Commands::Disks { target, format, json, max_disks } => {
+            let target = resolve_args(&target);
+            let stats = gather_stats(&connect_and_auth(&target)?)?;
+            // An empty list would look like a host without disks, so a script must see this fail
+            if !stats.available("disk") {
+                anyhow::bail!("Disk usage is unavailable on {}", target.host);
+            }
+            for mount in &stats.stale_mounts {
+                warn(&format!("{}: {} is stale or unreachable", target.host, mount));
+            }
+            if json {
+                println!("{}", redact(&disk_usage_json(&stats).to_string()));
+            } else {
+                println!("{}", redact(&get_disk_table(&stats, max_disks, format)));
+            }
+        }
+        Commands::Du { target, path, depth, top, format } => {
+            let target = resolve_args(&target);
+            let sess = connect_and_auth(&target)?;
+            println!("{}", redact(&get_du_table(&sess, &path, depth, top, format)?));
+        }
+        Commands::LogSources { target, since, top, format } => {
+            let target = resolve_args(&target);
+            let sess = connect_and_auth(&target)?;
+            println!("{}", redact(&get_log_sources_report(&sess, &since, top, format)?));
+        }
+        Commands::Updates { host, destination, username, port, hosts_file, concurrency, format } => {
+            let targets = match hosts_file {
+                Some(hosts_file) => read_hosts_file(&hosts_file)?,
+                None => vec![host.or(destination).expect("clap requires a host without --hosts-file")],
+            }
+            .into_iter()
+            .map(|host| {
+                let target = resolve(&host, username.clone(), port);
+                (host, target)
+            })
+            .collect();
+            let results = for_each_host(targets, concurrency, |target| {
+                Ok(pending_updates(&connect_and_auth(target)?)?)
+            })
+            .await?;
+            println!("{}", redact(&get_updates_table(&results, format)));
+            if results.iter().any(|(_, result)| result.is_err()) {
+                std::process::exit(1);
+            }
+        }
+        Commands::Check { target, cpu, mem, disk, format } => {
+            let target = resolve_args(&target);
+            let thresholds =
+                Thresholds { cpu, memory: mem, disk, disk_by_mount: config.disk_thresholds.clone().into_iter().collect() };
+            let (status, mut report) = match connect_and_auth(&target).and_then(|sess| gather_stats(&sess)) {
+                Ok(stats) => {
+                    warn_degraded(&target.host, &stats);
+                    health_check(&stats, &thresholds)
+                }
+                Err(e) => {
+                    let error = format!("{:#}", anyhow::Error::from(e));
+                    let status = CheckStatus::Unknown;
+                    (status, serde_json::json!({ "status": status, "failing": [], "error": error }))
+                }
+            };
+            // Nagios's WARNING, since an OK exit would get past --fail-on-warning
+            let status = match status {
+                CheckStatus::Ok if fail_on_warning && warned() => CheckStatus::Warning,
+                status => status,
+            };
+            report["status"] = serde_json::json!(status);
+            println!("{}", redact(&serialize_report(&report, format)?));
+            std::process::exit(status.exit_code());
+        }
+        Commands::Healthcheck { target, script, args } => {
+            let target = resolve_args(&target);
+            let name = script.display();
+            let status = match connect_and_auth(&target).and_then(|remote| run_script(&remote, &script, &args)) {
+                Ok(output) => {
+                    print!("{}", redact(&output.stdout));
+                    eprint!("{}", redact(&output.stderr));
+                    let (result, status) =
+                        if output.exit_status == 0 { ("PASS", CheckStatus::Ok) } else { ("FAIL", CheckStatus::Critical) };
+                    println!("{} {} on {} (exit status {})", result, name, redact(&target.host), output.exit_status);
+                    status
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", redact(&format!("{:#}", anyhow::Error::from(e))));
+                    println!("UNKNOWN {} on {} (could not run the script)", name, redact(&target.host));
+                    CheckStatus::Unknown
+                }
+            };
+            std::process::exit(status.exit_code());
+        }
+        Commands::WatchHost { target, interval, timeout } => {
+            let target = resolve_args(&target);
+            let availability = watch_host(&target, Duration::from_secs(interval), Duration::from_secs(timeout)).await?;
+            if !quiet {
+                println!("{}", redact(&availability.to_string()));
+            }
+        }
+        Commands::Reboot { target, wait, wait_timeout } => {
+            let target = resolve_args(&target);
+            reboot_host(&target, wait, Duration::from_secs(wait_timeout), quiet)?;
+        }
+        Commands::Completions { .. } => unreachable!("completions are printed before the config is read"),
+    }
+
+    Ok(())
+}
+
+// Reads commands from stdin and runs each on `remote`, streaming its output, until `exit` or
+// end of input. A failing command only ends its own line, not the session.
+fn repl(remote: &Remote, target: &Target) -> Result<()> {
+    let mut line = String::new();
+    loop {
+        print!("{}> ", redact(&target.host));
+        std::io::stdout().flush()?;
+        line.clear();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            println!();
+            return Ok(());
+        }
+        let command = line.trim();
+        match command {
+            "" => continue,
+            "exit" | "quit" => return Ok(()),
+            _ => {}
+        }
+        if let Some(word) = mutating_command(command).filter(|_| target.read_only) {
+            eprintln!("Refusing to run `{}` on {}: read-only mode is on", word, target.host);
+            continue;
+        }
+        // The prompt owns our stdin, so the command gets an empty one rather than waiting on it
+        let mut out = RedactWriter { inner: std::io::stdout(), pending: Vec::new() };
+        match exec_command(remote, command, &[], Some(&mut std::io::empty()), &mut out) {
+            Ok(0) => {}
+            Ok(status) => eprintln!("[exit status {}]", status),
+            Err(e) => eprintln!("Error: {}", redact(&format!("{:#}", anyhow::Error::from(e)))),
+        }
+        out.flush()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutating_commands_are_found_anywhere_in_the_line() {
+        assert_eq!(mutating_command("df -h; uptime"), None);
+        assert_eq!(mutating_command("systemctl status nginx"), None);
+        assert_eq!(mutating_command("systemctl restart nginx"), Some("systemctl"));
+        assert_eq!(mutating_command("echo x | sudo tee /etc/motd"), Some("sudo"));
+        assert_eq!(mutating_command("/sbin/reboot"), Some("/sbin/reboot"));
+        assert_eq!(mutating_command("ps aux&&kill 1234"), Some("kill"));
+    }
+
+    #[test]
+    fn healthcheck_scripts_are_scanned_outside_comments() {
+        assert_eq!(script_mutation("#!/bin/sh\n# don't reboot here\ncurl -fs localhost/health\n"), None);
+        assert_eq!(script_mutation("#!/bin/sh\ntest -f /run/app.pid || systemctl restart app\n"), Some("systemctl"));
+    }
+
+    #[test]
+    fn env_vars_split_on_the_first_equals_sign() {
+        assert_eq!(parse_env_var("OPTS=a=b"), Ok(("OPTS".to_string(), "a=b".to_string())));
+        assert_eq!(parse_env_var("EMPTY="), Ok(("EMPTY".to_string(), String::new())));
+        assert!(parse_env_var("=value").is_err());
+        assert!(parse_env_var("NOVALUE").is_err());
+    }
+
+    #[test]
+    fn completions_cover_subcommands_and_their_flags() {
+        let mut script = Vec::new();
+        clap_complete::generate(clap_complete::Shell::Bash, &mut Cli::command(), "remote_management", &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("remote_management__subcmd__monitor)"), "{}", script);
+        assert!(script.contains("--push-gateway"), "{}", script);
+        // The subcommand itself stays out of --help
+        let help = Cli::command().render_help().to_string();
+        assert!(!help.contains("completions"), "{}", help);
+    }
+}
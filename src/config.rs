@@ -0,0 +1,310 @@
+use crate::cli::ConnectionFlags;
+use crate::ssh::{PasswordSource, Target};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Deserialize, Default)]
+pub struct Config {
+    pub username: Option<String>,
+    pub port: Option<u16>,
+    pub shell: Option<String>,
+    pub command_prefix: Option<String>,
+    pub proxy_command: Option<String>,
+    pub proxy: Option<String>, // http://[user:pass@]host:port
+    pub unix_socket: Option<PathBuf>,
+    pub connect_timeout: Option<u64>, // seconds
+    pub banner_timeout: Option<u64>,  // seconds
+    pub command_timeout: Option<u64>, // seconds
+    pub cipher: Option<String>,
+    pub kex: Option<String>,
+    pub compress: Option<bool>,
+    pub read_only: Option<bool>,
+    pub history: Option<usize>,
+    pub utc: Option<bool>,
+    pub agent_socket: Option<PathBuf>, // unless --agent-socket is given
+    #[serde(default)]
+    pub identity: Vec<PathBuf>, // keys to try in order, unless --identity is given
+    pub theme_file: Option<PathBuf>, // colors for `monitor`, unless --theme-from-file is given
+    #[serde(default)]
+    pub disk_thresholds: HashMap<String, f64>, // mount pattern -> alert percent
+    #[serde(default)]
+    pub hosts: HashMap<String, HostProfile>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct HostProfile {
+    pub host: Option<String>,
+    pub username: Option<String>,
+    pub port: Option<u16>,
+    pub shell: Option<String>,
+    pub command_prefix: Option<String>,
+    pub proxy_command: Option<String>,
+    pub proxy: Option<String>, // http://[user:pass@]host:port
+    pub unix_socket: Option<PathBuf>,
+    pub connect_timeout: Option<u64>, // seconds
+    pub banner_timeout: Option<u64>,  // seconds
+    pub command_timeout: Option<u64>, // seconds
+    pub cipher: Option<String>,
+    pub kex: Option<String>,
+    pub compress: Option<bool>,
+    pub read_only: Option<bool>,
+    pub password: Option<PasswordSource>, // a reference to a secret, never the password itself
+    #[serde(default)]
+    pub identity: Vec<PathBuf>,
+}
+
+impl Config {
+    pub fn resolve(
+        &self,
+        host: &str,
+        username: Option<String>,
+        port: Option<u16>,
+        flags: &ConnectionFlags,
+    ) -> Target {
+        // -u and -P win over a user and port given with the host
+        let (destination_user, host, destination_port) = split_destination(host);
+        let username = username.or(destination_user);
+        let port = port.or(destination_port);
+        let profile = self.hosts.get(host);
+        Target {
+            host: profile
+                .and_then(|p| p.host.clone())
+                .unwrap_or_else(|| host.to_string()),
+            username: username
+                .or_else(|| profile.and_then(|p| p.username.clone()))
+                .or_else(|| self.username.clone())
+                .or_else(local_username),
+            port: port
+                .or_else(|| profile.and_then(|p| p.port))
+                .or(self.port)
+                .unwrap_or(22),
+            shell: flags
+                .shell
+                .clone()
+                .or_else(|| profile.and_then(|p| p.shell.clone()))
+                .or_else(|| self.shell.clone()),
+            command_prefix: flags
+                .command_prefix
+                .clone()
+                .or_else(|| profile.and_then(|p| p.command_prefix.clone()))
+                .or_else(|| self.command_prefix.clone()),
+            proxy_command: flags
+                .proxy_command
+                .clone()
+                .or_else(|| profile.and_then(|p| p.proxy_command.clone()))
+                .or_else(|| self.proxy_command.clone()),
+            http_proxy: flags
+                .proxy
+                .clone()
+                .or_else(|| profile.and_then(|p| p.proxy.clone()))
+                .or_else(|| self.proxy.clone()),
+            unix_socket: flags
+                .unix_socket
+                .clone()
+                .or_else(|| profile.and_then(|p| p.unix_socket.clone()))
+                .or_else(|| self.unix_socket.clone()),
+            connect_timeout: flags
+                .connect_timeout
+                .or_else(|| profile.and_then(|p| p.connect_timeout))
+                .or(self.connect_timeout)
+                .map(Duration::from_secs),
+            banner_timeout: flags
+                .banner_timeout
+                .or_else(|| profile.and_then(|p| p.banner_timeout))
+                .or(self.banner_timeout)
+                .map(Duration::from_secs),
+            command_timeout: flags
+                .command_timeout
+                .or_else(|| profile.and_then(|p| p.command_timeout))
+                .or(self.command_timeout)
+                .map(Duration::from_secs),
+            cipher: flags
+                .cipher
+                .clone()
+                .or_else(|| profile.and_then(|p| p.cipher.clone()))
+                .or_else(|| self.cipher.clone()),
+            kex: flags
+                .kex
+                .clone()
+                .or_else(|| profile.and_then(|p| p.kex.clone()))
+                .or_else(|| self.kex.clone()),
+            // Like read_only, a profile can turn off a global compress, and the flag wins
+            compress: flags.compress || profile.and_then(|p| p.compress).or(self.compress).unwrap_or(false),
+            // A profile can relax a global read_only, but the flag always wins
+            read_only: flags.read_only
+                || profile
+                    .and_then(|p| p.read_only)
+                    .or(self.read_only)
+                    .unwrap_or(false),
+            password: profile.and_then(|p| p.password.clone()),
+            prompt_once: flags.prompt_once,
+            // Lists aren't merged: the first of the flags, profile and config to name keys wins
+            identities: Some(flags.identity.clone())
+                .filter(|keys| !keys.is_empty())
+                .or_else(|| profile.map(|p| p.identity.clone()).filter(|keys| !keys.is_empty()))
+                .unwrap_or_else(|| self.identity.clone()),
+            known_hosts: flags.known_hosts.clone().or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".ssh").join("known_hosts"))
+            }),
+        }
+    }
+}
+
+// Splits ssh's `user@host:port` form, where the user and port are optional. An IPv6 address
+// needs brackets to carry a port ("[::1]:2222"); without them every colon belongs to the address.
+pub fn split_destination(destination: &str) -> (Option<String>, &str, Option<u16>) {
+    let (user, rest) = match destination.rsplit_once('@') {
+        Some((user, rest)) if !user.is_empty() => (Some(user.to_string()), rest),
+        _ => (None, destination),
+    };
+    if let Some((address, after)) = rest.strip_prefix('[').and_then(|r| r.split_once(']')) {
+        return (user, address, after.strip_prefix(':').and_then(|port| port.parse().ok()));
+    }
+    match rest.split_once(':') {
+        Some((host, port)) if !port.contains(':') => match port.parse() {
+            Ok(port) => (user, host, Some(port)),
+            Err(_) => (user, rest, None),
+        },
+        _ => (user, rest, None),
+    }
+}
+
+// The local login name, which ssh also uses when no user is given
+fn local_username() -> Option<String> {
+    username_from(|name| std::env::var(name).ok())
+}
+
+// Cron, systemd units and containers often set none of the variables, so the account name comes
+// from `whoami` then, rather than leaving a prompt that has no terminal to ask on
+fn username_from(var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    ["USER", "LOGNAME", "USERNAME"].into_iter().filter_map(var).find(|name| !name.is_empty()).or_else(|| {
+        let output = std::process::Command::new("whoami").output().ok().filter(|output| output.status.success())?;
+        // Windows answers with DOMAIN\user
+        let name = String::from_utf8_lossy(&output.stdout).trim().rsplit('\\').next()?.to_string();
+        (!name.is_empty()).then_some(name)
+    })
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("remote_management").join("config.toml"))
+}
+
+pub fn load_config(path: Option<&Path>) -> Result<Config> {
+    // An explicitly requested file must exist, but a missing default file just means no config
+    let path = match path {
+        Some(path) => {
+            if !path.exists() {
+                anyhow::bail!("Config file {} does not exist", path.display());
+            }
+            path.to_path_buf()
+        }
+        None => match default_config_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Config::default()),
+        },
+    };
+
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse config file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_resolution_prefers_flags_then_profile_then_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            username = "deploy"
+            port = 2200
+            identity = ["/keys/default"]
+            compress = true
+
+            [hosts.web1]
+            host = "web1.example.com"
+            port = 2222
+            identity = ["/keys/web", "/keys/old"]
+            compress = false
+            "#,
+        )
+        .unwrap();
+
+        let flags = ConnectionFlags::default();
+        let target = config.resolve("web1", None, None, &flags);
+        assert_eq!(target.host, "web1.example.com");
+        assert_eq!(target.username.as_deref(), Some("deploy"));
+        assert_eq!(target.port, 2222);
+        assert_eq!(target.identities, [PathBuf::from("/keys/web"), PathBuf::from("/keys/old")]);
+        assert_eq!(config.resolve("other", None, None, &flags).identities, [PathBuf::from("/keys/default")]);
+        assert!(!target.compress);
+        assert!(config.resolve("other", None, None, &flags).compress);
+
+        let target = config.resolve("web1", Some("root".to_string()), Some(22), &flags);
+        assert_eq!(target.username.as_deref(), Some("root"));
+        assert_eq!(target.port, 22);
+
+        assert_eq!(config.resolve("other", None, None, &flags).port, 2200);
+        assert_eq!(Config::default().resolve("other", None, None, &flags).username, local_username());
+
+        // user@host:port works with profile names too, and -u/-P still win
+        let target = config.resolve("admin@web1:2022", None, None, &flags);
+        assert_eq!(target.host, "web1.example.com");
+        assert_eq!(target.username.as_deref(), Some("admin"));
+        assert_eq!(target.port, 2022);
+        assert_eq!(config.resolve("admin@web1:2022", None, Some(22), &flags).port, 22);
+
+        let flags = ConnectionFlags { compress: true, ..flags };
+        assert!(config.resolve("web1", None, None, &flags).compress);
+    }
+
+    #[test]
+    fn username_falls_back_to_the_account_name() {
+        assert_eq!(username_from(|name| (name == "LOGNAME").then(|| "deploy".to_string())).as_deref(), Some("deploy"));
+        let whoami = std::process::Command::new("whoami").output().unwrap();
+        let expected = String::from_utf8(whoami.stdout).unwrap().trim().to_string();
+        assert_eq!(username_from(|_| None), Some(expected));
+    }
+
+    #[test]
+    fn profile_passwords_are_resolved_from_their_reference() {
+        let config: Config = toml::from_str(
+            r#"
+            [hosts.web1]
+            password = { command = "printf 's3cret\\n'" }
+
+            [hosts.web2]
+            password = { env = "REMOTE_MANAGEMENT_TEST_UNSET_PASSWORD" }
+            "#,
+        )
+        .unwrap();
+        let flags = ConnectionFlags::default();
+
+        let password = config.resolve("web1", None, None, &flags).password.unwrap();
+        assert_eq!(password.resolve().unwrap(), "s3cret");
+        let password = config.resolve("web2", None, None, &flags).password.unwrap();
+        assert!(password.resolve().unwrap_err().to_string().contains("REMOTE_MANAGEMENT_TEST_UNSET_PASSWORD"));
+        assert!(config.resolve("web3", None, None, &flags).password.is_none());
+
+        // A plaintext password is rejected rather than silently accepted
+        assert!(toml::from_str::<Config>("[hosts.web1]\npassword = \"hunter2\"").is_err());
+    }
+
+    #[test]
+    fn destinations_split_like_ssh() {
+        assert_eq!(split_destination("host"), (None, "host", None));
+        assert_eq!(split_destination("root@host"), (Some("root".to_string()), "host", None));
+        assert_eq!(split_destination("root@host:2222"), (Some("root".to_string()), "host", Some(2222)));
+        assert_eq!(split_destination("[::1]:2222"), (None, "::1", Some(2222)));
+        assert_eq!(split_destination("fe80::1"), (None, "fe80::1", None));
+        assert_eq!(split_destination("host:ssh"), (None, "host:ssh", None));
+    }
+}
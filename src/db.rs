@@ -0,0 +1,150 @@
+// Long-term storage of monitor samples in SQLite, for trends across sessions. Only available in
+// builds with the `sqlite` feature; without it, opening a database reports how to get one.
+
+use crate::stats::SystemStats;
+use anyhow::Result;
+use std::path::Path;
+
+/// One stored sample. Metrics the host couldn't report are None.
+pub struct StoredSample {
+    pub timestamp: i64, // seconds since the Unix epoch
+    pub cpu: Option<f64>,
+    pub memory: Option<f64>,
+    pub disk: Option<f64>, // fullest mount, percent
+    pub load: Option<f64>,
+}
+
+impl StoredSample {
+    pub fn from_stats(stats: &SystemStats, timestamp: i64) -> Self {
+        let metric = |name: &str, value: f64| Some(value).filter(|_| stats.available(name));
+        StoredSample {
+            timestamp,
+            cpu: metric("cpu", stats.cpu_usage),
+            memory: metric("memory", stats.memory_percent()),
+            disk: stats.fullest_disk().map(|(_, percent)| percent),
+            load: metric("load", stats.load_average.0),
+        }
+    }
+
+    /// Back into stats for the monitor's charts, as `replay` draws them. Memory is only stored
+    /// as a percentage, so it comes back as used bytes out of a nominal total.
+    pub fn to_stats(&self) -> SystemStats {
+        const NOMINAL_MEMORY: u64 = 10_000;
+        let missing = [("cpu", self.cpu), ("memory", self.memory), ("load", self.load)];
+        SystemStats {
+            cpu_usage: self.cpu.unwrap_or(0.0),
+            memory_total: if self.memory.is_some() { NOMINAL_MEMORY } else { 0 },
+            memory_used: self.memory.map_or(0, |percent| (percent / 100.0 * NOMINAL_MEMORY as f64).round() as u64),
+            load_average: (self.load.unwrap_or(0.0), 0.0, 0.0),
+            unavailable: missing.into_iter().filter(|(_, value)| value.is_none()).map(|(metric, _)| metric).collect(),
+            ..SystemStats::default()
+        }
+    }
+
+    /// The metrics with their column names, in display order.
+    pub fn metrics(&self) -> [(&'static str, Option<f64>); 4] {
+        [("CPU %", self.cpu), ("Memory %", self.memory), ("Fullest disk %", self.disk), ("Load (1m)", self.load)]
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub struct MetricsDb {
+    conn: rusqlite::Connection,
+}
+
+#[cfg(feature = "sqlite")]
+impl MetricsDb {
+    pub fn open(path: &Path) -> Result<Self> {
+        use anyhow::Context;
+
+        let conn = rusqlite::Connection::open(path)
+            .with_context(|| format!("Failed to open metrics database {}", path.display()))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS samples (
+                host TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                cpu REAL,
+                memory REAL,
+                disk REAL,
+                load REAL,
+                PRIMARY KEY (host, timestamp)
+            )",
+        )?;
+        Ok(MetricsDb { conn })
+    }
+
+    // A second sample within the same second replaces the first
+    pub fn record(&self, host: &str, sample: &StoredSample) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO samples (host, timestamp, cpu, memory, disk, load) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (host, timestamp) DO UPDATE SET
+                cpu = excluded.cpu, memory = excluded.memory, disk = excluded.disk, load = excluded.load",
+            rusqlite::params![host, sample.timestamp, sample.cpu, sample.memory, sample.disk, sample.load],
+        )?;
+        Ok(())
+    }
+
+    /// The samples stored for `host` at or after `since`, oldest first.
+    pub fn samples(&self, host: &str, since: i64) -> Result<Vec<StoredSample>> {
+        let mut statement = self.conn.prepare(
+            "SELECT timestamp, cpu, memory, disk, load FROM samples
+             WHERE host = ?1 AND timestamp >= ?2 ORDER BY timestamp",
+        )?;
+        let samples = statement
+            .query_map(rusqlite::params![host, since], |row| {
+                Ok(StoredSample {
+                    timestamp: row.get(0)?,
+                    cpu: row.get(1)?,
+                    memory: row.get(2)?,
+                    disk: row.get(3)?,
+                    load: row.get(4)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(samples)
+    }
+}
+
+// Without the feature there is nothing to construct, so the methods below can never be called
+#[cfg(not(feature = "sqlite"))]
+pub enum MetricsDb {}
+
+#[cfg(not(feature = "sqlite"))]
+impl MetricsDb {
+    pub fn open(_path: &Path) -> Result<Self> {
+        anyhow::bail!("This build has no metrics database support; rebuild with `--features sqlite`")
+    }
+
+    pub fn record(&self, _host: &str, _sample: &StoredSample) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn samples(&self, _host: &str, _since: i64) -> Result<Vec<StoredSample>> {
+        match *self {}
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn samples_are_stored_per_host_and_upserted_by_timestamp() {
+        let db = MetricsDb::open(Path::new(":memory:")).unwrap();
+        let mut stats = healthy_stats();
+        db.record("web1", &StoredSample::from_stats(&stats, 100)).unwrap();
+        db.record("web2", &StoredSample::from_stats(&stats, 100)).unwrap();
+        stats.cpu_usage = 80.0;
+        stats.unavailable.push("load");
+        db.record("web1", &StoredSample::from_stats(&stats, 100)).unwrap();
+        db.record("web1", &StoredSample::from_stats(&stats, 160)).unwrap();
+
+        let samples = db.samples("web1", 0).unwrap();
+        assert_eq!(samples.iter().map(|s| s.timestamp).collect::<Vec<_>>(), [100, 160]);
+        assert_eq!(samples[0].cpu, Some(80.0));
+        assert_eq!(samples[0].load, None);
+        assert_eq!(samples[0].disk, Some(95.0));
+        assert_eq!(db.samples("web1", 150).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,48 @@
+use crate::ssh::Target;
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+pub fn read_hosts_file(path: &Path) -> Result<Vec<String>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read hosts file {}", path.display()))?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+// Runs `task` against every host, with at most `concurrency` of them in flight at once.
+// Results are returned in the same order as `targets`.
+pub async fn for_each_host<T, F>(
+    targets: Vec<(String, Target)>,
+    concurrency: usize,
+    task: F,
+) -> Result<Vec<(String, Result<T>)>>
+where
+    T: Send + 'static,
+    F: Fn(&Target) -> Result<T> + Send + Sync + 'static,
+{
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let task = Arc::new(task);
+    let mut handles = Vec::with_capacity(targets.len());
+
+    for (name, target) in targets {
+        let permit = semaphore.clone().acquire_owned().await?;
+        let task = task.clone();
+        handles.push(tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let result = task(&target);
+            (name, result)
+        }));
+    }
+
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        results.push(handle.await?);
+    }
+    Ok(results)
+}
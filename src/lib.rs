@@ -0,0 +1,34 @@
+//! Gathering CPU, memory, disk and load statistics from a remote host over SSH.
+//!
+//! ```no_run
+//! use std::net::TcpStream;
+//!
+//! let mut sess = ssh2::Session::new()?;
+//! sess.set_tcp_stream(TcpStream::connect("server.example.com:22")?);
+//! sess.handshake()?;
+//! sess.userauth_agent("deploy")?;
+//!
+//! let stats = remote_management::gather_stats(&sess)?;
+//! println!("CPU {:.1}%, memory {:.0}%", stats.cpu_usage, stats.memory_percent());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+mod alerts;
+mod cast;
+mod cli;
+mod config;
+mod db;
+mod fleet;
+mod pushgateway;
+mod report;
+pub mod ssh;
+pub mod stats;
+mod tui;
+
+#[cfg(test)]
+mod test_support;
+
+/// Parses the command line and runs the selected subcommand; this is the whole binary.
+pub use cli::run;
+pub use ssh::{CommandOutput, RemoteExec, SshError};
+pub use stats::{gather_stats, parse_kernel_metrics, parse_os_release, parse_system_stats, KernelMetrics, MetricHistory, OsRelease, ReadonlyMount, StatsFallback, SystemStats, STATS_COMMANDS, STATS_FALLBACKS};
@@ -0,0 +1,125 @@
+// Pushing `status` results to a Prometheus Pushgateway, for cron jobs and other short-lived runs
+// that a Prometheus server can't scrape.
+
+use crate::stats::SystemStats;
+use anyhow::{Context, Result};
+use std::fmt::Write;
+
+// Label values are quoted, with backslashes, quotes and newlines escaped
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// The Prometheus text format of one host's stats. `remote_management_up` is 1 here; a host that
+/// couldn't be reached gets [`unreachable_metrics`] instead. Metrics the host couldn't report are
+/// left out rather than sent as zeros.
+pub fn prometheus_metrics(stats: &SystemStats) -> String {
+    let mut text = String::new();
+    let mut gauge = |name: &str, help: &str, samples: &[(String, f64)]| {
+        if samples.is_empty() {
+            return;
+        }
+        let _ = writeln!(text, "# HELP remote_management_{} {}", name, help);
+        let _ = writeln!(text, "# TYPE remote_management_{} gauge", name);
+        for (labels, value) in samples {
+            let _ = writeln!(text, "remote_management_{}{} {}", name, labels, value);
+        }
+    };
+    let single = |available: bool, value: f64| if available { vec![(String::new(), value)] } else { Vec::new() };
+    let mount = |mount: &str| format!("{{mount=\"{}\"}}", escape_label(mount));
+
+    gauge("up", "Whether the host could be reached and sampled.", &single(true, 1.0));
+    gauge("cpu_usage_percent", "CPU usage, in percent.", &single(stats.available("cpu"), stats.cpu_usage));
+    gauge("cpu_steal_percent", "CPU time taken by the hypervisor for other guests, in percent.", &single(stats.available("cpu"), stats.cpu_steal));
+    gauge("cpu_cores", "Cores the host (or its container's CPU quota) has.", &single(stats.cpu_count > 0, stats.cpu_count as f64));
+    let memory = stats.available("memory");
+    gauge("memory_total_bytes", "Memory the host has.", &single(memory, stats.memory_total as f64));
+    gauge("memory_used_bytes", "Memory in use.", &single(memory, stats.memory_used as f64));
+    gauge("swap_total_bytes", "Swap space the host has.", &single(memory, stats.swap_total as f64));
+    gauge("swap_used_bytes", "Swap space in use.", &single(memory, stats.swap_used as f64));
+    let (one, five, fifteen) = stats.load_average;
+    let load = if stats.available("load") {
+        vec![("{period=\"1m\"}".to_string(), one), ("{period=\"5m\"}".to_string(), five), ("{period=\"15m\"}".to_string(), fifteen)]
+    } else {
+        Vec::new()
+    };
+    gauge("load_average", "Load average over the period.", &load);
+    gauge("uptime_seconds", "Seconds since the host booted.", &stats.uptime_seconds.map(|s| vec![(String::new(), s as f64)]).unwrap_or_default());
+    let disks = |value: fn(&(String, u64, u64)) -> u64| -> Vec<(String, f64)> {
+        stats.disk_usage.iter().map(|disk| (mount(&disk.0), value(disk) as f64)).collect()
+    };
+    gauge("disk_total_bytes", "Size of the filesystem mounted here.", &disks(|(_, total, _)| *total));
+    gauge("disk_used_bytes", "Space used on the filesystem mounted here.", &disks(|(_, _, used)| *used));
+    let stale: Vec<(String, f64)> = stats.stale_mounts.iter().map(|m| (mount(m), 1.0)).collect();
+    gauge("disk_stale", "Mounts df listed without sizes, like a stale NFS handle.", &stale);
+    let readonly: Vec<(String, f64)> = stats.readonly_mounts.iter().map(|ro| (mount(&ro.mount), 1.0)).collect();
+    gauge("disk_readonly", "Writable filesystems that are mounted read-only, like after I/O errors.", &readonly);
+    gauge("tcp_established", "TCP connections in the ESTABLISHED state.", &stats.tcp_established.map(|n| vec![(String::new(), n as f64)]).unwrap_or_default());
+    gauge("oom_kills", "Processes the OOM killer has killed since boot.", &stats.oom_kills.map(|n| vec![(String::new(), n as f64)]).unwrap_or_default());
+    text
+}
+
+/// What to push for a host that couldn't be reached, so an alert on `remote_management_up == 0`
+/// fires instead of the host's last pushed figures lingering.
+pub fn unreachable_metrics() -> String {
+    "# HELP remote_management_up Whether the host could be reached and sampled.\n\
+     # TYPE remote_management_up gauge\n\
+     remote_management_up 0\n"
+        .to_string()
+}
+
+// The Pushgateway URL of a job's group for one host. The host becomes the `host` label of every
+// metric in the group; a value that can't go in a URL path as is is sent base64-encoded.
+pub fn group_url(gateway: &str, job: &str, host: &str) -> String {
+    use base64::Engine;
+    let segment = |name: &str, value: &str| {
+        if !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || "-._:".contains(c)) {
+            format!("{}/{}", name, value)
+        } else {
+            format!("{}@base64/{}", name, base64::engine::general_purpose::URL_SAFE.encode(value))
+        }
+    };
+    format!("{}/metrics/{}/{}", gateway.trim_end_matches('/'), segment("job", job), segment("host", host))
+}
+
+/// Replaces the host's group with `metrics` (a PUT, so metrics the host stopped reporting go away).
+pub async fn push_metrics(client: &reqwest::Client, gateway: &str, job: &str, host: &str, metrics: String) -> Result<()> {
+    let url = group_url(gateway, job, host);
+    client
+        .put(&url)
+        .header(reqwest::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(metrics)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .with_context(|| format!("Failed to push metrics to {}", url))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn stats_are_formatted_as_prometheus_gauges() {
+        let mut stats = healthy_stats();
+        stats.unavailable.push("load");
+        let text = prometheus_metrics(&stats);
+        assert!(text.starts_with("# HELP remote_management_up "), "{}", text);
+        assert!(text.contains("\nremote_management_up 1\n"), "{}", text);
+        assert!(text.contains("\n# TYPE remote_management_memory_used_bytes gauge\nremote_management_memory_used_bytes 2000000000\n"), "{}", text);
+        assert!(text.contains("\nremote_management_disk_used_bytes{mount=\"/data\"} 190000000000\n"), "{}", text);
+        assert!(text.contains("\nremote_management_tcp_established 5\n"), "{}", text);
+        // Unavailable metrics are left out, HELP and TYPE lines included
+        assert!(!text.contains("load_average"), "{}", text);
+        assert!(unreachable_metrics().ends_with("\nremote_management_up 0\n"));
+        assert_eq!(escape_label("a\"b\\c"), "a\\\"b\\\\c");
+    }
+
+    #[test]
+    fn hosts_become_a_label_in_the_group_url() {
+        assert_eq!(group_url("http://pg:9091/", "host-check", "web1.example.com"), "http://pg:9091/metrics/job/host-check/host/web1.example.com");
+        assert_eq!(group_url("http://pg:9091", "nightly", "deploy@web1"), "http://pg:9091/metrics/job/nightly/host@base64/ZGVwbG95QHdlYjE=");
+    }
+}
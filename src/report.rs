@@ -0,0 +1,1967 @@
+use crate::cli::{DataFormat, ExecFormat, HostSort, OutputFormat, ProcessSort, TableStyle};
+use crate::db::StoredSample;
+use crate::ssh::{connect_and_auth, note, shell_quote, CommandOutput, RemoteExec, SshError, Target, PROGRESS};
+use crate::stats::{gather_stats, parse_cgroup, parse_readonly_mounts, parse_system_stats, OsRelease, SystemStats, CGROUP_COMMAND, MOUNTS_COMMAND, STATS_COMMANDS};
+use anyhow::Result;
+use humansize::{format_size, BINARY};
+use indicatif::ProgressBar;
+use prettytable::{format::consts, Cell, Row, Table};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+// Dumps exactly what the metric commands return, for debugging the parser
+pub fn get_raw_output(sess: &impl RemoteExec) -> Result<String, SshError> {
+    let mut report = String::new();
+    for cmd in STATS_COMMANDS {
+        let output = sess.run_command_output(cmd)?;
+        report.push_str(&format!("$ {}\n", cmd));
+        report.push_str(&output.stdout);
+        if !output.stderr.is_empty() {
+            report.push_str("--- stderr ---\n");
+            report.push_str(&output.stderr);
+        }
+        report.push_str(&format!("--- exit status {} ---\n\n", output.exit_status));
+    }
+    Ok(report)
+}
+
+// The journalctl command for entries at `priority` or above as JSON, narrowed by any of the rest
+pub fn journal_command(priority: &str, since: Option<&str>, unit: Option<&str>, lines: Option<usize>) -> String {
+    let mut command = format!("journalctl --no-pager -o json -p {}", shell_quote(priority));
+    if let Some(since) = since {
+        command.push_str(&format!(" --since {}", shell_quote(since)));
+    }
+    if let Some(unit) = unit {
+        command.push_str(&format!(" -u {}", shell_quote(unit)));
+    }
+    if let Some(lines) = lines {
+        command.push_str(&format!(" -n {}", lines));
+    }
+    command
+}
+
+// Whether timestamps are printed as RFC 3339 in UTC (--utc or utc in the config) rather than in
+// local time, chosen once from the command line and config
+pub static UTC_TIMESTAMPS: OnceLock<bool> = OnceLock::new();
+
+// A timestamp for output: RFC 3339 in UTC with --utc, otherwise local time in `local_format`
+pub fn format_time(time: chrono::DateTime<chrono::Utc>, local_format: &str) -> String {
+    format_time_in(time, local_format, UTC_TIMESTAMPS.get().copied().unwrap_or(false))
+}
+
+fn format_time_in(time: chrono::DateTime<chrono::Utc>, local_format: &str, utc: bool) -> String {
+    if utc {
+        time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    } else {
+        time.with_timezone(&chrono::Local).format(local_format).to_string()
+    }
+}
+
+// Whether warnings are printed, chosen once from the command line: --quiet keeps them off stderr
+// unless --fail-on-warning makes them failures
+pub static SHOW_WARNINGS: OnceLock<bool> = OnceLock::new();
+
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Prints a warning on stderr: something a report got around but that is worth knowing, like a
+/// metric no command could read. With --fail-on-warning, a run that warned exits non-zero.
+pub fn warn(message: &str) {
+    WARNED.store(true, Ordering::SeqCst);
+    if SHOW_WARNINGS.get().copied().unwrap_or(true) {
+        note(&redact(&format!("Warning: {}", message)));
+    }
+}
+
+/// Whether [`warn`] has been called during this run.
+pub fn warned() -> bool {
+    WARNED.load(Ordering::SeqCst)
+}
+
+/// Warns about what a sample is missing: metrics neither their command nor its fallback could
+/// read, and mounts that didn't answer.
+pub fn warn_degraded(host: &str, stats: &SystemStats) {
+    if !stats.unavailable.is_empty() {
+        warn(&format!("{}: no data for {}", host, stats.unavailable.join(", ")));
+    }
+    for mount in &stats.stale_mounts {
+        warn(&format!("{}: {} is stale or unreachable", host, mount));
+    }
+    for ro in &stats.readonly_mounts {
+        warn(&format!("{}: {} has been remounted read-only", host, ro.mount));
+    }
+}
+
+// Hostnames (and with `--redact-ips`, IP addresses) to hide from output behind placeholders,
+// set once from the command line with --redact
+pub static REDACTION: OnceLock<Redaction> = OnceLock::new();
+
+pub struct Redaction {
+    hosts: Mutex<Vec<(String, String)>>, // (name, placeholder)
+    ips: bool,
+}
+
+impl Redaction {
+    pub fn new(ips: bool) -> Self {
+        Redaction { hosts: Mutex::new(Vec::new()), ips }
+    }
+
+    // Registers the names one host goes by (as typed, as resolved, ...) under a single
+    // placeholder, numbered in the order hosts are first seen
+    pub fn add_host(&self, names: &[&str]) -> String {
+        let mut hosts = self.hosts.lock().unwrap();
+        let placeholder = names
+            .iter()
+            .find_map(|name| hosts.iter().find(|(known, _)| known == name).map(|(_, placeholder)| placeholder.clone()))
+            .unwrap_or_else(|| {
+                let count = hosts.iter().map(|(_, placeholder)| placeholder).collect::<std::collections::HashSet<_>>().len();
+                format!("host-{}", count + 1)
+            });
+        for name in names {
+            if !name.is_empty() && !hosts.iter().any(|(known, _)| known == name) {
+                hosts.push((name.to_string(), placeholder.clone()));
+            }
+        }
+        // Longest first, so "web1.example.com" goes before "web1" gets a chance at it
+        hosts.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+        placeholder
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (name, placeholder) in self.hosts.lock().unwrap().iter() {
+            text = replace_word(&text, name, placeholder);
+        }
+        if self.ips {
+            text = scrub_ips(&text);
+        }
+        text
+    }
+}
+
+// `text` with --redact applied, or unchanged without it
+pub fn redact(text: &str) -> String {
+    match REDACTION.get() {
+        Some(redaction) => redaction.apply(text),
+        None => text.to_string(),
+    }
+}
+
+// Replaces `name` where it stands on its own, so "web1" leaves "web10" and "web1-backup" alone
+fn replace_word(text: &str, name: &str, replacement: &str) -> String {
+    let is_word = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, _) in text.match_indices(name) {
+        let end = start + name.len();
+        if start < last
+            || text[..start].chars().next_back().is_some_and(is_word)
+            || text[end..].chars().next().is_some_and(is_word)
+        {
+            continue;
+        }
+        out.push_str(&text[last..start]);
+        out.push_str(replacement);
+        last = end;
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+// Replaces IPv4 and IPv6 addresses with "[ip]". Only strings that parse as addresses count, so
+// times like 10:00:00 and hex ids are left alone; a port after an IPv4 address survives.
+fn scrub_ips(text: &str) -> String {
+    let is_address_char = |c: char| c.is_ascii_hexdigit() || c == ':' || c == '.';
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(is_address_char) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let len = rest.find(|c: char| !is_address_char(c)).unwrap_or(rest.len());
+        let (run, after) = rest.split_at(len);
+        // Part of a longer word, like the "de" in "node"
+        if out.chars().next_back().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            out.push_str(run);
+        } else {
+            let address = run.trim_end_matches(['.', ':']);
+            if address.len() > 2 && address.parse::<std::net::Ipv6Addr>().is_ok() {
+                out.push_str("[ip]");
+                out.push_str(&run[address.len()..]);
+            } else {
+                let parts: Vec<String> = run
+                    .split(':')
+                    .map(|part| {
+                        let address = part.trim_end_matches('.');
+                        if address.parse::<std::net::Ipv4Addr>().is_ok() {
+                            format!("[ip]{}", &part[address.len()..])
+                        } else {
+                            part.to_string()
+                        }
+                    })
+                    .collect();
+                out.push_str(&parts.join(":"));
+            }
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Passes a stream through [`redact`] a line at a time, for streamed command output.
+pub struct RedactWriter<W: Write> {
+    pub inner: W,
+    pub pending: Vec<u8>,
+}
+
+impl<W: Write> Write for RedactWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // Without --redact there's nothing to hold back, and prompts without a newline show up
+        if REDACTION.get().is_none() {
+            return self.inner.write(buf);
+        }
+        self.pending.extend_from_slice(buf);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            self.inner.write_all(redact(&String::from_utf8_lossy(&line)).as_bytes())?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        // A last line without a newline
+        if !self.pending.is_empty() {
+            let rest: Vec<u8> = std::mem::take(&mut self.pending);
+            self.inner.write_all(redact(&String::from_utf8_lossy(&rest)).as_bytes())?;
+        }
+        self.inner.flush()
+    }
+}
+
+// Turns one line of `journalctl -o json` into a syslog-style line
+pub fn format_journal_entry(line: &str, color: bool) -> Option<String> {
+    let entry: serde_json::Value = serde_json::from_str(line).ok()?;
+    let field = |name: &str| entry.get(name).and_then(|v| v.as_str());
+    let time = field("__REALTIME_TIMESTAMP")
+        .and_then(|micros| micros.parse::<i64>().ok())
+        .and_then(chrono::DateTime::from_timestamp_micros)
+        .map(|t| format_time(t, "%b %d %H:%M:%S"))
+        .unwrap_or_default();
+    let source = match (field("SYSLOG_IDENTIFIER").or(field("_COMM")), field("_PID")) {
+        (Some(name), Some(pid)) => format!("{}[{}]", name, pid),
+        (Some(name), None) => name.to_string(),
+        (None, _) => "unknown".to_string(),
+    };
+    // Messages that aren't valid UTF-8 are exported as an array of bytes
+    let message = field("MESSAGE").unwrap_or("[binary message]");
+    let text = format!("{} {}: {}", time, source, message);
+
+    if !color {
+        return Some(text);
+    }
+    let foreground_color = match field("PRIORITY").and_then(|p| p.parse::<u8>().ok()).unwrap_or(6) {
+        0..=3 => crossterm::style::Color::Red,
+        4 => crossterm::style::Color::Yellow,
+        5 => crossterm::style::Color::Cyan,
+        _ => return Some(text),
+    };
+    let style = crossterm::style::ContentStyle { foreground_color: Some(foreground_color), ..Default::default() };
+    Some(style.apply(text).to_string())
+}
+
+// Formats journal JSON into readable lines as it streams through, one complete line at a time
+pub struct JournalWriter<W: Write> {
+    pub inner: W,
+    pub pending: Vec<u8>,
+    pub color: bool,
+}
+
+impl<W: Write> Write for JournalWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            if let Some(text) = format_journal_entry(&String::from_utf8_lossy(&line), self.color) {
+                writeln!(self.inner, "{}", text)?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// A spinner on stderr for commands that would otherwise sit silent while they connect and
+// gather; hidden when asked to be quiet or when stderr isn't a terminal
+pub fn start_spinner(enabled: bool) -> ProgressBar {
+    use std::io::IsTerminal;
+    let spinner = if enabled && std::io::stderr().is_terminal() { ProgressBar::new_spinner() } else { ProgressBar::hidden() };
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    let _ = PROGRESS.set(spinner.clone());
+    spinner
+}
+
+/// Runs commands through `inner`, showing each one on `spinner` while it runs.
+pub struct WithSpinner<'a, R> {
+    pub inner: &'a R,
+    pub spinner: &'a ProgressBar,
+}
+
+impl<R: RemoteExec> RemoteExec for WithSpinner<'_, R> {
+    fn run_command_output(&self, command: &str) -> Result<CommandOutput, SshError> {
+        self.spinner.set_message(format!("Running {}", command));
+        self.inner.run_command_output(command)
+    }
+
+    fn os_release(&self) -> Result<Option<OsRelease>, SshError> {
+        self.spinner.set_message("Detecting the distribution");
+        self.inner.os_release()
+    }
+
+    fn container_runtime(&self) -> Result<Option<String>, SshError> {
+        self.spinner.set_message("Checking for a container");
+        self.inner.container_runtime()
+    }
+}
+
+pub fn get_server_status(
+    target: &Target,
+    raw: bool,
+    all: bool,
+    format: OutputFormat,
+    min_uptime: Option<Duration>,
+    baseline: Option<&Snapshot>,
+    spinner: &ProgressBar,
+) -> Result<String> {
+    spinner.set_message(format!("Connecting to {}", target.host));
+    let remote = connect_and_auth(target)?;
+    let sess = WithSpinner { inner: &remote, spinner };
+    if raw {
+        return Ok(get_raw_output(&sess)?);
+    }
+    let mut status = get_system_info(&sess, format, min_uptime)?;
+    if all {
+        status.push_str(&get_readonly_mounts(&sess, format)?);
+        status.push_str(&get_memory_hogs(&sess, format)?);
+        status.push_str(&get_oom_events(&sess, format)?);
+        status.push_str(&get_updates_report(&sess, format)?);
+        status.push_str(&get_login_report(&sess, format)?);
+    }
+    if let Some(baseline) = baseline {
+        status.push_str(&get_snapshot_diff(baseline, &gather_stats(&sess)?, format));
+    }
+    Ok(status)
+}
+
+/// The parts of a saved `status --format json` that `status --compare-to` diffs against. Fields
+/// missing from the file read as zero or none.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Snapshot {
+    pub memory_total: u64,
+    pub memory_used: u64,
+    pub swap_used: u64,
+    pub disk_usage: Vec<(String, u64, u64)>,
+    pub load_average: (f64, f64, f64),
+    pub cpu_count: usize,
+    pub uptime_seconds: Option<u64>,
+    pub tcp_established: Option<u64>,
+    pub os: Option<OsRelease>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    pub saved_at: Option<chrono::DateTime<chrono::Utc>>, // when the file was last written
+}
+
+impl Snapshot {
+    pub fn load(path: &Path) -> Result<Self> {
+        use anyhow::Context;
+        let contents =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read snapshot {}", path.display()))?;
+        let mut snapshot: Snapshot = serde_json::from_str(&contents)
+            .with_context(|| format!("{} is not a `status --format json` snapshot of one host", path.display()))?;
+        if let Some(error) = &snapshot.error {
+            anyhow::bail!("Snapshot {} has no stats, the host failed when it was taken: {}", path.display(), error);
+        }
+        snapshot.saved_at = std::fs::metadata(path).and_then(|m| m.modified()).ok().map(Into::into);
+        Ok(snapshot)
+    }
+}
+
+// "+2 GiB", "-512 MiB", for the change in a byte count
+fn signed_size(before: u64, now: u64) -> String {
+    let sign = if now >= before { '+' } else { '-' };
+    format!("{}{}", sign, format_size(now.abs_diff(before), BINARY))
+}
+
+// What changed between a saved snapshot and now. Only changed metrics are listed; the ones worth
+// a look (a new or missing mount, a reboot, a big jump) are highlighted.
+pub fn get_snapshot_diff(before: &Snapshot, now: &SystemStats, format: OutputFormat) -> String {
+    let mut table = ReportTable::new(&["Metric", "Before", "Now", "Change"]);
+    let mut row = |highlight: bool, cells: [String; 4]| {
+        if highlight {
+            table.add_highlighted_row(cells.to_vec());
+        } else {
+            table.add_row(cells.to_vec());
+        }
+    };
+    let tenth_of = |total: u64, before: u64, now: u64| total > 0 && before.abs_diff(now) >= total / 10;
+    let size = |bytes: u64| format_size(bytes, BINARY);
+
+    if before.os != now.os {
+        let os = |os: Option<&OsRelease>| os.map_or_else(|| "unknown".to_string(), OsRelease::to_string);
+        row(true, ["OS".to_string(), os(before.os.as_ref()), os(now.os.as_ref()), "changed".to_string()]);
+    }
+    if let (Some(before_uptime), Some(now_uptime)) = (before.uptime_seconds, now.uptime_seconds) {
+        if now_uptime < before_uptime {
+            row(true, ["Uptime".to_string(), format_uptime(before_uptime), format_uptime(now_uptime), "rebooted".to_string()]);
+        }
+    }
+    if before.cpu_count != now.cpu_count && now.available("cores") {
+        let change = format!("{:+}", now.cpu_count as i64 - before.cpu_count as i64);
+        row(true, ["Cores".to_string(), before.cpu_count.to_string(), now.cpu_count.to_string(), change]);
+    }
+    if now.available("memory") {
+        if before.memory_total != now.memory_total {
+            let change = signed_size(before.memory_total, now.memory_total);
+            row(true, ["Memory total".to_string(), size(before.memory_total), size(now.memory_total), change]);
+        }
+        if before.memory_used != now.memory_used {
+            let change = signed_size(before.memory_used, now.memory_used);
+            let highlight = tenth_of(now.memory_total, before.memory_used, now.memory_used);
+            row(highlight, ["Memory used".to_string(), size(before.memory_used), size(now.memory_used), change]);
+        }
+        if before.swap_used != now.swap_used {
+            let change = signed_size(before.swap_used, now.swap_used);
+            let highlight = tenth_of(now.swap_total, before.swap_used, now.swap_used);
+            row(highlight, ["Swap used".to_string(), size(before.swap_used), size(now.swap_used), change]);
+        }
+    }
+    let (load_before, load_now) = (before.load_average.0, now.load_average.0);
+    if now.available("load") && format!("{:.2}", load_before) != format!("{:.2}", load_now) {
+        // Half the cores' worth of extra load is a different machine to work on
+        let highlight = (load_now - load_before).abs() >= (now.cpu_count as f64 / 2.0).max(1.0);
+        let cells = ["Load (1m)".to_string(), format!("{:.2}", load_before), format!("{:.2}", load_now), format!("{:+.2}", load_now - load_before)];
+        row(highlight, cells);
+    }
+    if let (Some(before_count), Some(now_count)) = (before.tcp_established, now.tcp_established) {
+        if before_count != now_count {
+            let change = format!("{:+}", now_count as i64 - before_count as i64);
+            row(false, ["Connections".to_string(), before_count.to_string(), now_count.to_string(), change]);
+        }
+    }
+    if now.available("disk") {
+        for (mount, total, used) in &now.disk_usage {
+            let label = format!("Disk {}", mount);
+            match before.disk_usage.iter().find(|(before_mount, _, _)| before_mount == mount) {
+                None => row(true, [label, "-".to_string(), format!("{} of {}", size(*used), size(*total)), "new mount".to_string()]),
+                Some((_, _, before_used)) if before_used != used => {
+                    let highlight = tenth_of(*total, *before_used, *used);
+                    row(highlight, [label, size(*before_used), size(*used), signed_size(*before_used, *used)]);
+                }
+                Some(_) => {}
+            }
+        }
+        for (mount, total, used) in &before.disk_usage {
+            if !now.disk_usage.iter().any(|(now_mount, _, _)| now_mount == mount) {
+                let label = format!("Disk {}", mount);
+                row(true, [label, format!("{} of {}", size(*used), size(*total)), "-".to_string(), "gone".to_string()]);
+            }
+        }
+    }
+
+    let since = before.saved_at.map_or_else(|| "the snapshot".to_string(), |at| format!("the snapshot from {}", format_time(at, "%Y-%m-%d %H:%M:%S")));
+    if table.rows.is_empty() {
+        return format!("\nNo changes since {}\n", since);
+    }
+    format!("\nChanges since {}:\n{}", since, table.render(format))
+}
+
+// Border style for `--format table` output, chosen once from the command line
+pub static TABLE_STYLE: OnceLock<TableStyle> = OnceLock::new();
+
+// A table that renders either through prettytable or as a GitHub-flavored Markdown table.
+// A row with fewer cells than the header has its last cell span the remaining columns.
+pub struct ReportTable {
+    pub header: Vec<String>,
+    pub rows: Vec<(Vec<String>, bool)>, // (cells, highlighted)
+}
+
+impl ReportTable {
+    fn new(header: &[&str]) -> Self {
+        ReportTable {
+            header: header.iter().map(|h| h.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    fn add_row(&mut self, cells: Vec<String>) {
+        self.rows.push((cells, false));
+    }
+
+    fn add_highlighted_row(&mut self, cells: Vec<String>) {
+        self.rows.push((cells, true));
+    }
+
+    fn render(&self, format: OutputFormat) -> String {
+        let style = TABLE_STYLE.get().copied().unwrap_or(TableStyle::Default);
+        // Redact the cells rather than the rendered table, so columns are sized for the placeholders
+        if let Some(redaction) = REDACTION.get() {
+            let rows = self
+                .rows
+                .iter()
+                .map(|(cells, highlighted)| (cells.iter().map(|cell| redaction.apply(cell)).collect(), *highlighted))
+                .collect();
+            return ReportTable { header: self.header.clone(), rows }.render_styled(format, style);
+        }
+        self.render_styled(format, style)
+    }
+
+    fn render_styled(&self, format: OutputFormat, style: TableStyle) -> String {
+        match format {
+            // Tables with more columns already spread their data across the width
+            OutputFormat::TableWide if self.header.len() == 2 => {
+                let width = crossterm::terminal::size().map_or(80, |(columns, _)| columns as usize);
+                self.render_sections(width)
+            }
+            OutputFormat::TableWide => self.render_styled(OutputFormat::Table, style),
+            OutputFormat::Table if style == TableStyle::Markdown => self.render_styled(OutputFormat::Markdown, style),
+            OutputFormat::Table => {
+                let mut table = Table::new();
+                table.set_format(match style {
+                    TableStyle::Default | TableStyle::Markdown => *consts::FORMAT_DEFAULT,
+                    TableStyle::Compact => *consts::FORMAT_NO_LINESEP_WITH_TITLE,
+                    TableStyle::Clean => *consts::FORMAT_CLEAN,
+                    TableStyle::Borderless => *consts::FORMAT_NO_BORDER_LINE_SEPARATOR,
+                    TableStyle::Box => *consts::FORMAT_BOX_CHARS,
+                });
+                table.set_titles(Row::new(self.header.iter().map(|h| Cell::new(h)).collect()));
+                for (cells, highlighted) in &self.rows {
+                    let span = self.header.len().saturating_sub(cells.len()) + 1;
+                    let row = cells
+                        .iter()
+                        .enumerate()
+                        .map(|(i, text)| {
+                            let cell = Cell::new(text).style_spec(if *highlighted { "Fr" } else { "" });
+                            if i == cells.len() - 1 { cell.with_hspan(span) } else { cell }
+                        })
+                        .collect();
+                    table.add_row(Row::new(row));
+                }
+                table.to_string()
+            }
+            OutputFormat::Markdown => {
+                let escape = |text: &str| text.trim().replace('|', "\\|").replace('\n', "<br>");
+                let mut out = format!("| {} |\n", self.header.join(" | "));
+                out.push_str(&format!("|{}\n", " --- |".repeat(self.header.len())));
+                for (cells, highlighted) in &self.rows {
+                    let cells: Vec<String> = (0..self.header.len())
+                        .map(|i| {
+                            let text = cells.get(i).map(|c| escape(c)).unwrap_or_default();
+                            if *highlighted && !text.is_empty() { format!("**{}**", text) } else { text }
+                        })
+                        .collect();
+                    out.push_str(&format!("| {} |\n", cells.join(" | ")));
+                }
+                out
+            }
+        }
+    }
+
+    // Each row as a rule carrying its first cell, `width` columns long, with the rest of the row
+    // below it unwrapped. Highlighted rows get a heavy rule.
+    fn render_sections(&self, width: usize) -> String {
+        let mut out = String::new();
+        for (cells, highlighted) in &self.rows {
+            let Some((title, values)) = cells.split_first() else {
+                continue;
+            };
+            let rule = if *highlighted { "━" } else { "─" };
+            let lead = format!("{0}{0} {1} ", rule, title);
+            let fill = width.saturating_sub(lead.chars().count());
+            out.push_str(&format!("{}{}\n", lead, rule.repeat(fill)));
+            for value in values {
+                out.push_str(value.trim_end());
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+pub fn section_title(title: &str, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Table | OutputFormat::TableWide => format!("\n{}\n", title),
+        OutputFormat::Markdown => format!("\n### {}\n\n", title),
+    }
+}
+
+pub struct LoginEntry {
+    pub user: String,
+    pub source: String,
+    pub time: String,
+}
+
+// Failed attempts from a single source at or above this count are highlighted as a likely brute-force
+pub const FAILED_LOGIN_SPIKE: usize = 10;
+
+// Parses `last -i`/`lastb -i` output: user, tty, source address, then the free-form time columns
+pub fn parse_last(output: &str) -> Vec<LoginEntry> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 4 || matches!(parts[0], "reboot" | "shutdown" | "wtmp" | "btmp") {
+                return None;
+            }
+            Some(LoginEntry {
+                user: parts[0].to_string(),
+                source: parts[2].to_string(),
+                time: parts[3..].join(" "),
+            })
+        })
+        .collect()
+}
+
+pub fn get_login_report(sess: &impl RemoteExec, format: OutputFormat) -> Result<String, SshError> {
+    let mut report = String::new();
+
+    let recent = parse_last(&sess.run_command("last -i -n 20")?);
+    let mut table = ReportTable::new(&["User", "Source", "Time"]);
+    for entry in recent {
+        table.add_row(vec![entry.user, entry.source, entry.time]);
+    }
+    report.push_str(&section_title("Recent logins", format));
+    report.push_str(&table.render(format));
+
+    // lastb reads /var/log/btmp, which is usually only readable by root
+    let failed = sess.run_command_output("lastb -i -n 200")?;
+    report.push_str(&section_title("Failed logins", format));
+    if failed.exit_status != 0 {
+        report.push_str(&format!("unavailable: {}\n", failed.stderr.trim()));
+        return Ok(report);
+    }
+
+    let mut by_source: Vec<(String, usize, String)> = Vec::new();
+    for entry in parse_last(&failed.stdout) {
+        match by_source.iter_mut().find(|(source, _, _)| *source == entry.source) {
+            Some((_, count, _)) => *count += 1,
+            // lastb lists newest first, so the first entry seen is the latest attempt
+            None => by_source.push((entry.source, 1, entry.time)),
+        }
+    }
+    by_source.sort_by_key(|(_, count, _)| std::cmp::Reverse(*count));
+
+    let mut table = ReportTable::new(&["Source", "Attempts", "Latest"]);
+    for (source, count, latest) in by_source {
+        let cells = vec![source, count.to_string(), latest];
+        if count >= FAILED_LOGIN_SPIKE {
+            table.add_highlighted_row(cells);
+        } else {
+            table.add_row(cells);
+        }
+    }
+    report.push_str(&table.render(format));
+    Ok(report)
+}
+
+pub struct CronJob {
+    pub schedule: String, // the five time fields, or a keyword like @reboot
+    pub user: String,
+    pub command: String,
+    pub source: String, // the crontab file, or "crontab -l" for a user's own
+}
+
+/// Prints every crontab the login can read, each after a "### <kind> <name>" marker: the system
+/// files, the scripts run-parts runs from /etc/cron.{hourly,daily,weekly,monthly}, and each user's
+/// crontab. Other users' crontabs need root, which the "### unprivileged" marker says is missing.
+pub const CRON_COMMAND: &str = "\
+for f in /etc/crontab /etc/cron.d/*; do [ -f \"$f\" ] && echo \"### file $f\" && cat \"$f\"; done; \
+for p in hourly daily weekly monthly; do [ -d /etc/cron.$p ] && echo \"### dir $p\" && ls -1 /etc/cron.$p; done; \
+me=$(id -un); [ \"$(id -u)\" = 0 ] || echo '### unprivileged'; \
+for u in $(cut -d: -f1 /etc/passwd); do \
+if [ \"$u\" = \"$me\" ]; then c=$(crontab -l 2>/dev/null); else c=$(crontab -l -u \"$u\" 2>/dev/null); fi \
+&& [ -n \"$c\" ] && echo \"### user $u\" && echo \"$c\"; done; true";
+
+// Splits off `count` whitespace-separated fields and returns them with the rest of the line,
+// whose own spacing is kept since it's a shell command
+fn split_fields(line: &str, count: usize) -> Option<(Vec<&str>, &str)> {
+    let mut fields = Vec::with_capacity(count);
+    let mut rest = line.trim_start();
+    for _ in 0..count {
+        let end = rest.find(char::is_whitespace)?;
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    (!rest.is_empty()).then_some((fields, rest))
+}
+
+// One crontab line. System crontabs name the user after the schedule; a user's own crontab doesn't.
+fn parse_cron_line(line: &str, owner: Option<&str>, source: &str) -> Option<CronJob> {
+    let line = line.trim();
+    let first = line.split_whitespace().next()?;
+    // Comments, and variable assignments like MAILTO=root or PATH=...
+    if first.starts_with('#') || (first.contains('=') && !first.starts_with('@')) {
+        return None;
+    }
+    let schedule_fields = if first.starts_with('@') { 1 } else { 5 };
+    let (fields, command) = split_fields(line, schedule_fields + usize::from(owner.is_none()))?;
+    let user = match owner {
+        Some(owner) => owner,
+        None => fields[schedule_fields],
+    };
+    Some(CronJob {
+        schedule: fields[..schedule_fields].join(" "),
+        user: user.to_string(),
+        command: command.to_string(),
+        source: source.to_string(),
+    })
+}
+
+/// Parses [`CRON_COMMAND`]'s output into jobs, and whether other users' crontabs were left out
+/// for want of root.
+pub fn parse_crontabs(output: &str) -> (Vec<CronJob>, bool) {
+    let mut jobs = Vec::new();
+    let mut unprivileged = false;
+    let mut section: Option<(&str, &str)> = None;
+    for line in output.lines() {
+        if let Some(marker) = line.strip_prefix("### ") {
+            section = marker.split_once(' ');
+            unprivileged |= marker == "unprivileged";
+            continue;
+        }
+        let job = match section {
+            Some(("file", path)) => parse_cron_line(line, None, path),
+            Some(("user", user)) => parse_cron_line(line, Some(user), "crontab -l"),
+            // run-parts skips names with dots, like dpkg's leftover "*.dpkg-old" files
+            Some(("dir", period)) if !line.trim().is_empty() && !line.contains('.') => Some(CronJob {
+                schedule: format!("@{}", period),
+                user: "root".to_string(),
+                command: format!("/etc/cron.{}/{}", period, line.trim()),
+                source: format!("/etc/cron.{}", period),
+            }),
+            _ => None,
+        };
+        jobs.extend(job);
+    }
+    (jobs, unprivileged)
+}
+
+pub fn get_cron_report(sess: &impl RemoteExec, format: OutputFormat) -> Result<String, SshError> {
+    let (jobs, unprivileged) = parse_crontabs(&sess.run_command(CRON_COMMAND)?);
+    let mut report = if jobs.is_empty() {
+        "No cron jobs found\n".to_string()
+    } else {
+        let mut table = ReportTable::new(&["Schedule", "User", "Command", "Source"]);
+        for job in jobs {
+            table.add_row(vec![job.schedule, job.user, job.command, job.source]);
+        }
+        table.render(format)
+    };
+    if unprivileged {
+        report.push_str("\nOther users' crontabs need root to read and are not listed\n");
+    }
+    Ok(report)
+}
+
+pub struct ProcessInfo {
+    pub user: String,
+    pub pid: u32,
+    pub cpu: f64,
+    pub mem: f64,
+    pub rss_kib: u64,
+    pub command: String,
+}
+
+// Parses `ps aux`: USER PID %CPU %MEM VSZ RSS TTY STAT START TIME COMMAND, where the
+// command may itself contain spaces
+pub fn parse_ps_aux(output: &str) -> Vec<ProcessInfo> {
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 11 {
+                return None;
+            }
+            Some(ProcessInfo {
+                user: parts[0].to_string(),
+                pid: parts[1].parse().ok()?,
+                cpu: parts[2].parse().unwrap_or(0.0),
+                mem: parts[3].parse().unwrap_or(0.0),
+                rss_kib: parts[5].parse().unwrap_or(0),
+                command: parts[10..].join(" "),
+            })
+        })
+        .collect()
+}
+
+pub fn sort_processes(processes: &mut [ProcessInfo], sort: ProcessSort) {
+    match sort {
+        ProcessSort::Cpu => processes.sort_by(|a, b| b.cpu.total_cmp(&a.cpu)),
+        ProcessSort::Mem => processes.sort_by_key(|p| std::cmp::Reverse(p.rss_kib)),
+        ProcessSort::Pid => processes.sort_by_key(|p| p.pid),
+    }
+}
+
+pub fn get_process_table(
+    sess: &impl RemoteExec,
+    filter: Option<&str>,
+    owner: Option<&str>,
+    sort: ProcessSort,
+    max_procs: Option<usize>,
+    format: OutputFormat,
+) -> Result<String, SshError> {
+    let mut processes: Vec<ProcessInfo> = parse_ps_aux(&sess.run_command("ps aux")?)
+        .into_iter()
+        .filter(|p| filter.is_none_or(|f| p.command.contains(f)))
+        .filter(|p| owner.is_none_or(|o| p.user == o))
+        .collect();
+    sort_processes(&mut processes, sort);
+    let hidden = processes.len().saturating_sub(max_procs.unwrap_or(usize::MAX));
+    processes.truncate(processes.len() - hidden);
+
+    let mut table = ReportTable::new(&["PID", "User", "CPU %", "MEM %", "RSS", "Command"]);
+    for p in processes {
+        table.add_row(vec![
+            p.pid.to_string(),
+            p.user,
+            format!("{:.1}", p.cpu),
+            format!("{:.1}", p.mem),
+            format_size(p.rss_kib * 1024, BINARY),
+            p.command,
+        ]);
+    }
+    if hidden > 0 {
+        table.add_row(vec![format!("... and {} more", hidden)]);
+    }
+    Ok(table.render(format))
+}
+
+// "240 GiB used of 300 GiB (80%) across 2 filesystems", the figure for capacity reports
+pub fn disk_totals_line(stats: &SystemStats) -> Option<String> {
+    let (total, used, filesystems) = stats.disk_totals()?;
+    Some(format!(
+        "{} used of {} ({:.0}%) across {} filesystem{}",
+        format_size(used, BINARY),
+        format_size(total, BINARY),
+        disk_percent(total, used),
+        filesystems,
+        if filesystems == 1 { "" } else { "s" }
+    ))
+}
+
+pub fn get_disk_table(stats: &SystemStats, max_disks: Option<usize>, format: OutputFormat) -> String {
+    let mut table = ReportTable::new(&["Mount", "Size", "Used", "Use %"]);
+    let (disks, hidden) = stats.fullest_disks(max_disks);
+    for (mount, total, used) in disks {
+        table.add_row(vec![
+            mount.clone(),
+            format_size(*total, BINARY),
+            format_size(*used, BINARY),
+            format!("{:.0}%", disk_percent(*total, *used)),
+        ]);
+    }
+    // A stale mount is a problem in itself, so it stands out instead of reading as 0% used
+    for mount in &stats.stale_mounts {
+        table.add_highlighted_row(vec![mount.clone(), "-".to_string(), "-".to_string(), "unavailable".to_string()]);
+    }
+    if hidden > 0 {
+        table.add_row(vec![format!("... and {} more", hidden)]);
+    }
+    let mut report = table.render(format);
+    if let Some(line) = disk_totals_line(stats) {
+        report.push_str(&format!("\nTotal: {}", line));
+    }
+    report
+}
+
+// `du` limited to one filesystem and `depth` levels, in KiB, largest first. -d and -k work with
+// both GNU and busybox du. Unreadable directories are left out rather than failing the listing.
+pub fn du_command(path: &str, depth: usize, top: usize) -> String {
+    // One extra line for `path` itself, which is always the largest
+    format!("du -xk -d {} {} 2>/dev/null | sort -rn | head -n {}", depth, shell_quote(path), top + 1)
+}
+
+// "<KiB>\t<path>" lines into (bytes, path)
+pub fn parse_du(output: &str) -> Vec<(u64, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (size, path) = line.split_once('\t')?;
+            Some((size.trim().parse::<u64>().ok()? * 1024, path.to_string()))
+        })
+        .collect()
+}
+
+pub fn get_du_table(
+    sess: &impl RemoteExec,
+    path: &str,
+    depth: usize,
+    top: usize,
+    format: OutputFormat,
+) -> Result<String, SshError> {
+    let mut entries = parse_du(&sess.run_command(&du_command(path, depth, top))?);
+    entries.sort_by_key(|(size, _)| std::cmp::Reverse(*size));
+    let Some((total, _)) = entries.first().cloned() else {
+        return Ok(format!("Nothing readable under {}", path));
+    };
+
+    let mut table = ReportTable::new(&["Size", &format!("% of {}", path), "Directory"]);
+    for (size, dir) in entries.into_iter().skip(1).take(top) {
+        table.add_row(vec![format_size(size, BINARY), format!("{:.0}%", disk_percent(total, size)), dir]);
+    }
+    Ok(format!("{} in {}\n{}", format_size(total, BINARY), path, table.render(format)))
+}
+
+/// What takes up /var/log, each part after a "### <part>" marker: the directory's total and
+/// its `top` largest entries in KiB, journald's own figure for its files, and the `top` units
+/// that logged the most since `since`. journald doesn't keep sizes per unit, so those are the
+/// bytes of each entry's JSON form (close to what it stores) and entry counts, summed on the host
+/// so only the totals come back. Entries without a unit, like the kernel's, go by their syslog
+/// identifier. Older journalctl puts spaces around the colons, hence the `" *: *"`.
+pub fn log_sources_command(since: &str, top: usize) -> String {
+    format!(
+        "echo '### total'; du -sk /var/log 2>/dev/null; \
+         echo '### files'; du -sk /var/log/* 2>/dev/null | sort -rn | head -n {top}; \
+         echo '### journal'; journalctl --disk-usage 2>/dev/null; \
+         echo '### units'; journalctl -q --since {since} -o json 2>/dev/null | awk '\
+         {{ u = \"-\"; if (match($0, /\"_SYSTEMD_UNIT\" *: *\"[^\"]*\"/) || match($0, /\"SYSLOG_IDENTIFIER\" *: *\"[^\"]*\"/)) \
+         {{ u = substr($0, RSTART, RLENGTH); sub(/^[^:]*: *\"/, \"\", u); sub(/\"$/, \"\", u) }} \
+         bytes[u] += length($0) + 1; entries[u]++ }} \
+         END {{ for (u in bytes) printf \"%d\\t%d\\t%s\\n\", bytes[u], entries[u], u }}' | sort -rn | head -n {top}; true",
+        since = shell_quote(since),
+        top = top,
+    )
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct LogSources {
+    pub total: Option<u64>,             // bytes under /var/log
+    pub files: Vec<(u64, String)>,      // bytes, path
+    pub journal: Option<u64>,           // bytes of journal files, None without journald
+    pub units: Vec<(u64, u64, String)>, // bytes logged, entries, unit
+}
+
+// journalctl --disk-usage's "Archived and active journals take up 1.2G in the file system."
+// systemd sizes count in 1024s, with B, K, M, G or T after the number
+fn parse_journal_usage(line: &str) -> Option<u64> {
+    let size = line.split("take up ").nth(1)?.split_whitespace().next()?;
+    let (number, unit) = size.split_at(size.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(size.len()));
+    let power = ["B", "K", "M", "G", "T"].iter().position(|u| *u == unit).unwrap_or(0);
+    Some((number.parse::<f64>().ok()? * 1024f64.powi(power as i32)) as u64)
+}
+
+/// Parses [`log_sources_command`]'s output.
+pub fn parse_log_sources(output: &str) -> LogSources {
+    let mut sources = LogSources::default();
+    let mut section = "";
+    for line in output.lines() {
+        if let Some(marker) = line.strip_prefix("### ") {
+            section = marker;
+            continue;
+        }
+        match section {
+            "total" => sources.total = parse_du(line).first().map(|(size, _)| *size),
+            "files" => sources.files.extend(parse_du(line)),
+            "journal" => sources.journal = sources.journal.or(parse_journal_usage(line)),
+            "units" => {
+                let mut fields = line.splitn(3, '\t');
+                if let (Some(Ok(bytes)), Some(Ok(entries)), Some(unit)) =
+                    (fields.next().map(str::parse), fields.next().map(str::parse), fields.next())
+                {
+                    sources.units.push((bytes, entries, unit.to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+    sources
+}
+
+pub fn get_log_sources_report(sess: &impl RemoteExec, since: &str, top: usize, format: OutputFormat) -> Result<String, SshError> {
+    let sources = parse_log_sources(&sess.run_command(&log_sources_command(since, top))?);
+    let mut report = match sources.total {
+        Some(total) if !sources.files.is_empty() => {
+            let mut table = ReportTable::new(&["Size", "% of /var/log", "Path"]);
+            for (size, path) in sources.files {
+                table.add_row(vec![format_size(size, BINARY), format!("{:.0}%", disk_percent(total, size)), path]);
+            }
+            format!("{} in /var/log\n{}", format_size(total, BINARY), table.render(format))
+        }
+        _ => "Nothing readable under /var/log\n".to_string(),
+    };
+    let Some(journal) = sources.journal else {
+        report.push_str("\nNo journald on this host (or journalctl isn't readable by this login)\n");
+        return Ok(report);
+    };
+    report.push_str(&format!("\n{} of journal files\n", format_size(journal, BINARY)));
+    if sources.units.is_empty() {
+        report.push_str(&format!("No journal entries since {}\n", since));
+        return Ok(report);
+    }
+    let mut table = ReportTable::new(&["Logged", "Entries", "Unit"]);
+    for (bytes, entries, unit) in sources.units {
+        table.add_row(vec![format_size(bytes, BINARY), entries.to_string(), unit]);
+    }
+    report.push_str(&format!("Logged since {} (estimated from entry sizes)\n{}", since, table.render(format)));
+    Ok(report)
+}
+
+// The same mounts as get_disk_table with unformatted byte counts, for capacity scripts. Stale
+// mounts come last with null figures and `"unavailable": true`.
+pub fn disk_usage_json(stats: &SystemStats) -> serde_json::Value {
+    stats
+        .disk_usage
+        .iter()
+        .map(|(mount, total, used)| {
+            serde_json::json!({
+                "mount": mount,
+                "total": total,
+                "used": used,
+                "percent": (disk_percent(*total, *used) * 10.0).round() / 10.0,
+            })
+        })
+        .chain(stats.stale_mounts.iter().map(|mount| {
+            serde_json::json!({ "mount": mount, "total": null, "used": null, "percent": null, "unavailable": true })
+        }))
+        .collect()
+}
+
+fn disk_percent(total: u64, used: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        used as f64 / total as f64 * 100.0
+    }
+}
+
+pub fn format_timestamp(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|t| format_time(t, "%Y-%m-%d %H:%M:%S"))
+        .unwrap_or_default()
+}
+
+// Every stored sample, or with `summary` the min/average/max of each metric over them
+pub fn get_history_report(host: &str, samples: &[StoredSample], summary: bool, format: OutputFormat) -> String {
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+        return format!("No samples stored for {}", host);
+    };
+    let names = first.metrics().map(|(name, _)| name);
+
+    if !summary {
+        let mut header = vec!["Time"];
+        header.extend(names);
+        let mut table = ReportTable::new(&header);
+        for sample in samples {
+            let mut row = vec![format_timestamp(sample.timestamp)];
+            row.extend(sample.metrics().map(|(_, value)| value.map_or("n/a".to_string(), |v| format!("{:.1}", v))));
+            table.add_row(row);
+        }
+        return table.render(format);
+    }
+
+    let mut table = ReportTable::new(&["Metric", "Min", "Average", "Max"]);
+    for (i, name) in names.into_iter().enumerate() {
+        let values: Vec<f64> = samples.iter().filter_map(|s| s.metrics()[i].1).collect();
+        if values.is_empty() {
+            table.add_row(vec![name.to_string(), "n/a".to_string()]);
+            continue;
+        }
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let average = values.iter().sum::<f64>() / values.len() as f64;
+        table.add_row(vec![name.to_string(), format!("{:.1}", min), format!("{:.1}", average), format!("{:.1}", max)]);
+    }
+    format!(
+        "{} samples for {} from {} to {}\n{}",
+        samples.len(),
+        host,
+        format_timestamp(first.timestamp),
+        format_timestamp(last.timestamp),
+        table.render(format)
+    )
+}
+
+pub const MEMORY_HOGS_COMMAND: &str = "ps -eo pid,user,rss,comm --sort=-rss | head -n 6";
+
+// The five processes with the largest resident set, the likely culprits when memory runs low
+pub fn get_memory_hogs(sess: &impl RemoteExec, format: OutputFormat) -> Result<String, SshError> {
+    let output = sess.run_command_output(MEMORY_HOGS_COMMAND)?;
+    let mut report = section_title("Top memory consumers", format);
+    let processes: Vec<(&str, &str, u64, String)> = output
+        .stdout
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            // comm can contain spaces ("Web Content")
+            let [pid, user, rss, command @ ..] = parts.as_slice() else {
+                return None;
+            };
+            Some((*pid, *user, rss.parse().ok()?, command.join(" ")))
+        })
+        .collect();
+    // head hides ps's exit status, so an empty list is the only sign that ps failed (BusyBox ps has no --sort)
+    if processes.is_empty() {
+        report.push_str(&format!("unavailable: {}\n", output.stderr.trim()));
+        return Ok(report);
+    }
+
+    let mut table = ReportTable::new(&["PID", "User", "RSS", "Command"]);
+    for (pid, user, rss_kib, command) in processes {
+        table.add_row(vec![pid.to_string(), user.to_string(), format_size(rss_kib * 1024, BINARY), command]);
+    }
+    report.push_str(&table.render(format));
+    Ok(report)
+}
+
+// dmesg usually needs root these days, so the kernel journal is tried next
+pub const OOM_EVENTS_COMMAND: &str = "{ dmesg -T 2>/dev/null || journalctl -k -q --no-pager -o short-iso 2>/dev/null; } | grep -i 'killed process' | tail -n 10";
+
+pub struct OomEvent {
+    pub time: String,
+    pub pid: String,
+    pub process: String,
+}
+
+// Kernel lines like "[Mon Oct 12 10:00:00 2026] Out of memory: Killed process 1234 (java) ..."
+// from dmesg -T, or "2026-10-12T10:00:00+0000 web1 kernel: ..." from journalctl
+pub fn parse_oom_events(output: &str) -> Vec<OomEvent> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (prefix, killed) = line.split_once("Killed process ")?;
+            let time = match prefix.strip_prefix('[') {
+                Some(rest) => rest.split_once(']')?.0,
+                None => prefix.split_whitespace().next()?,
+            };
+            let (pid, rest) = killed.split_once(' ')?;
+            let process = rest.strip_prefix('(')?.split_once(')')?.0;
+            Some(OomEvent { time: time.trim().to_string(), pid: pid.to_string(), process: process.to_string() })
+        })
+        .collect()
+}
+
+// Writable filesystems the kernel has made read-only, first in `status --all` since writes to
+// them are already failing
+pub fn get_readonly_mounts(sess: &impl RemoteExec, format: OutputFormat) -> Result<String, SshError> {
+    let mounts = parse_readonly_mounts(&sess.run_command(MOUNTS_COMMAND)?);
+    let mut report = section_title("Read-only filesystems", format);
+    if mounts.is_empty() {
+        report.push_str("None; every writable filesystem is mounted read-write\n");
+        return Ok(report);
+    }
+
+    let mut table = ReportTable::new(&["Mount", "Device", "Filesystem"]);
+    for ro in mounts {
+        warn(&format!("{} has been remounted read-only", ro.mount));
+        table.add_highlighted_row(vec![ro.mount, ro.device, ro.fstype]);
+    }
+    report.push_str(&table.render(format));
+    report.push_str("Usually the kernel's response to I/O errors; check `dmesg` and the disk's health before remounting\n");
+    Ok(report)
+}
+
+// The most recent processes the kernel killed for running out of memory
+pub fn get_oom_events(sess: &impl RemoteExec, format: OutputFormat) -> Result<String, SshError> {
+    let events = parse_oom_events(&sess.run_command(OOM_EVENTS_COMMAND)?);
+    let mut report = section_title("OOM kills", format);
+    if events.is_empty() {
+        report.push_str("None in the kernel log (reading it may need root)\n");
+        return Ok(report);
+    }
+
+    let mut table = ReportTable::new(&["Time", "PID", "Process"]);
+    for event in events {
+        table.add_highlighted_row(vec![event.time, event.pid, event.process]);
+    }
+    report.push_str(&table.render(format));
+    Ok(report)
+}
+
+// dnf replaced yum on Fedora and RHEL 8, but CentOS 7 hosts still only have yum. The listings
+// are split by a --- line; check-update's own output never contains one.
+pub const APT_UPDATES_COMMAND: &str = "apt list --upgradable 2>/dev/null";
+pub const DNF_UPDATES_COMMAND: &str = "pm=$(command -v dnf || command -v yum); $pm -q check-update 2>/dev/null; echo ---; $pm -q updateinfo list --security 2>/dev/null";
+
+/// Package updates waiting to be installed on a host.
+#[derive(Debug, PartialEq)]
+pub struct PendingUpdates {
+    pub total: usize,
+    pub security: usize,
+}
+
+// Lines like "openssl/jammy-updates,jammy-security 3.0.2-0ubuntu1.15 amd64 [upgradable from: ...]";
+// an update is a security one when it comes from a -security suite
+pub fn parse_apt_updates(output: &str) -> PendingUpdates {
+    let upgradable: Vec<&str> = output.lines().filter(|line| line.contains("[upgradable from")).collect();
+    let security = upgradable
+        .iter()
+        .filter(|line| line.split_once('/').is_some_and(|(_, rest)| rest.split(' ').next().unwrap_or("").contains("-security")))
+        .count();
+    PendingUpdates { total: upgradable.len(), security }
+}
+
+// check-update prints "name.arch version repo" per package, then any "Obsoleting Packages";
+// updateinfo prints "advisory severity package" per fix, so a package can appear more than once
+pub fn parse_dnf_updates(output: &str) -> PendingUpdates {
+    let (updates, advisories) = output.split_once("\n---\n").unwrap_or((output, ""));
+    let total = updates
+        .lines()
+        .take_while(|line| !line.starts_with("Obsoleting"))
+        .filter(|line| line.split_whitespace().count() == 3)
+        .count();
+    let mut packages: Vec<&str> = advisories
+        .lines()
+        .filter_map(|line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [_, _, package] => Some(package),
+            _ => None,
+        })
+        .collect();
+    packages.sort_unstable();
+    packages.dedup();
+    PendingUpdates { total, security: packages.len() }
+}
+
+/// Counts the host's pending updates with its distribution's package manager, or returns
+/// None when that isn't apt, dnf or yum.
+pub fn pending_updates(sess: &impl RemoteExec) -> Result<Option<PendingUpdates>, SshError> {
+    let os = sess.os_release()?;
+    let updates = match os.as_ref().and_then(OsRelease::package_manager) {
+        Some("apt") => parse_apt_updates(&sess.run_command(APT_UPDATES_COMMAND)?),
+        Some("dnf") => parse_dnf_updates(&sess.run_command(DNF_UPDATES_COMMAND)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(updates))
+}
+
+const UPDATES_UNSUPPORTED: &str = "n/a (no apt, dnf or yum)";
+
+pub fn get_updates_report(sess: &impl RemoteExec, format: OutputFormat) -> Result<String, SshError> {
+    let mut report = section_title("Package updates", format);
+    match pending_updates(sess)? {
+        Some(updates) => {
+            let mut table = ReportTable::new(&["Pending", "Security"]);
+            let row = vec![updates.total.to_string(), updates.security.to_string()];
+            if updates.security > 0 {
+                table.add_highlighted_row(row);
+            } else {
+                table.add_row(row);
+            }
+            report.push_str(&table.render(format));
+        }
+        None => report.push_str(&format!("{}\n", UPDATES_UNSUPPORTED)),
+    }
+    Ok(report)
+}
+
+// Hosts with security updates waiting are highlighted and counted below the table
+pub fn get_updates_table(results: &[(String, Result<Option<PendingUpdates>>)], format: OutputFormat) -> String {
+    let mut table = ReportTable::new(&["Host", "Pending", "Security"]);
+    let mut exposed = 0;
+    for (host, result) in results {
+        match result {
+            Ok(Some(updates)) if updates.security > 0 => {
+                exposed += 1;
+                table.add_highlighted_row(vec![host.clone(), updates.total.to_string(), updates.security.to_string()]);
+            }
+            Ok(Some(updates)) => table.add_row(vec![host.clone(), updates.total.to_string(), updates.security.to_string()]),
+            Ok(None) => table.add_row(vec![host.clone(), UPDATES_UNSUPPORTED.to_string()]),
+            Err(e) => table.add_row(vec![host.clone(), format!("Error: {:#}", e)]),
+        }
+    }
+
+    let mut report = table.render(format);
+    if exposed > 0 {
+        report.push_str(&format!("\n{} of {} hosts have pending security updates", exposed, results.len()));
+    }
+    report
+}
+
+// A short span like "45s", "12m 5s" or "3h 20m"
+pub fn format_duration(seconds: u64) -> String {
+    if seconds >= 3600 {
+        format!("{}h {}m", seconds / 3600, seconds % 3600 / 60)
+    } else if seconds >= 60 {
+        format!("{}m {}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+// "5d 2h", "2h 3m" or "4m"
+pub fn format_uptime(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// One host's stats for `--format json|yaml`: the [`SystemStats`] fields next to the host name,
+/// or an error when the host couldn't be queried.
+#[derive(Serialize)]
+pub struct HostStatus<'a> {
+    pub host: &'a str,
+    #[serde(flatten)]
+    pub stats: Option<&'a SystemStats>,
+    pub error: Option<String>,
+}
+
+impl<'a> From<&'a (String, Result<SystemStats>)> for HostStatus<'a> {
+    fn from((host, result): &'a (String, Result<SystemStats>)) -> Self {
+        HostStatus { host, stats: result.as_ref().ok(), error: result.as_ref().err().map(|e| format!("{:#}", e)) }
+    }
+}
+
+// JSON stays on one line, as `check` has always printed it
+pub fn serialize_report(value: &impl Serialize, format: DataFormat) -> Result<String> {
+    Ok(match format {
+        DataFormat::Json => serde_json::to_string(value)?,
+        DataFormat::Yaml => serde_yaml::to_string(value)?.trim_end().to_string(),
+    })
+}
+
+// Puts hosts that couldn't be reached first, then the highest values of the metric; hosts
+// missing the metric go last. Ties keep the hosts file's order.
+pub fn sort_fleet(results: &mut [(String, Result<SystemStats>)], by: HostSort) {
+    let metric = |stats: &SystemStats| match by {
+        HostSort::Cpu => Some(stats.cpu_usage).filter(|_| stats.available("cpu")),
+        HostSort::Mem => Some(stats.memory_percent()).filter(|percent| stats.available("memory") && percent.is_finite()),
+        HostSort::Disk => stats.fullest_disk().map(|(_, percent)| percent),
+        HostSort::Name => None,
+    };
+    let key = |result: &Result<SystemStats>| match result {
+        Err(_) => (0, 0.0),
+        Ok(stats) => metric(stats).map_or((2, 0.0), |value| (1, -value)),
+    };
+    results.sort_by(|(name_a, a), (name_b, b)| match by {
+        HostSort::Name => name_a.cmp(name_b),
+        _ => {
+            let ((rank_a, value_a), (rank_b, value_b)) = (key(a), key(b));
+            rank_a.cmp(&rank_b).then(value_a.total_cmp(&value_b))
+        }
+    });
+}
+
+pub fn get_fleet_table(
+    results: &[(String, Result<SystemStats>)],
+    format: OutputFormat,
+    min_uptime: Option<Duration>,
+) -> String {
+    let mut table = ReportTable::new(&["Host", "OS", "CPU", "Memory", "Fullest disk", "Load (1m)", "Uptime"]);
+
+    for (host, result) in results {
+        match result {
+            Ok(stats) => {
+                let disk = stats
+                    .fullest_disk()
+                    .map(|(mount, percent)| format!("{} {:.0}%", mount, percent))
+                    .unwrap_or_else(|| "-".to_string());
+                let cell = |metric: &str, value: String| if stats.available(metric) { value } else { "n/a".to_string() };
+                let rebooted = min_uptime.is_some_and(|window| stats.booted_within(window));
+                let mut uptime = stats.uptime_seconds.map_or_else(|| "n/a".to_string(), format_uptime);
+                if rebooted {
+                    uptime.push_str(" (recent reboot)");
+                }
+                let row = vec![
+                    host.clone(),
+                    stats.os.as_ref().map_or_else(|| "-".to_string(), OsRelease::to_string),
+                    cell("cpu", format!("{:.1}%", stats.cpu_usage)),
+                    cell("memory", format!("{:.0}%", stats.memory_percent())),
+                    cell("disk", disk),
+                    cell("load", format!("{:.2}", stats.load_average.0)),
+                    uptime,
+                ];
+                if rebooted {
+                    table.add_highlighted_row(row);
+                } else {
+                    table.add_row(row);
+                }
+            }
+            Err(e) => {
+                table.add_row(vec![host.clone(), format!("Error: {:#}", e)]);
+            }
+        }
+    }
+
+    table.render(format)
+}
+
+// Groups each host's output under a header; hosts that exited non-zero or couldn't run the
+// command at all are marked FAILED
+pub fn get_exec_report(results: &[(String, Result<CommandOutput>)], format: ExecFormat) -> String {
+    if let ExecFormat::Json = format {
+        let hosts: Vec<_> = results
+            .iter()
+            .map(|(host, result)| match result {
+                Ok(output) => serde_json::json!({
+                    "host": host,
+                    "exit_status": output.exit_status,
+                    "stdout": output.stdout,
+                    "stderr": output.stderr,
+                    "error": null,
+                }),
+                Err(e) => serde_json::json!({
+                    "host": host,
+                    "exit_status": null,
+                    "stdout": null,
+                    "stderr": null,
+                    "error": format!("{:#}", e),
+                }),
+            })
+            .collect();
+        return serde_json::to_string_pretty(&hosts).expect("JSON values always serialize");
+    }
+
+    let mut report = String::new();
+    let mut failed = 0;
+    for (host, result) in results {
+        match result {
+            Ok(output) => {
+                if output.exit_status == 0 {
+                    report.push_str(&format!("=== {} ===\n", host));
+                } else {
+                    failed += 1;
+                    report.push_str(&format!("=== {} FAILED (exit status {}) ===\n", host, output.exit_status));
+                }
+                for text in [&output.stdout, &output.stderr] {
+                    report.push_str(text);
+                    if !text.is_empty() && !text.ends_with('\n') {
+                        report.push('\n');
+                    }
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                report.push_str(&format!("=== {} FAILED ===\nError: {:#}\n", host, e));
+            }
+        }
+        report.push('\n');
+    }
+    report.push_str(&format!("{} of {} hosts failed", failed, results.len()));
+    report
+}
+
+pub fn get_system_info(
+    sess: &impl RemoteExec,
+    format: OutputFormat,
+    min_uptime: Option<Duration>,
+) -> Result<String, SshError> {
+    let commands = vec![
+        "uptime",
+        "free -h",
+        "df -h",
+        "top -bn1 | head -n 3",
+    ];
+
+    let mut table = ReportTable::new(&["Metric", "Value"]);
+    if let Some(os) = sess.os_release()? {
+        table.add_row(vec!["OS".to_string(), os.to_string()]);
+    }
+    // The raw `free` and `top` output below is the machine's; the cgroup says what the container gets
+    if let Some(runtime) = sess.container_runtime()? {
+        let mut limits = SystemStats::default();
+        limits.apply_cgroup(runtime, &parse_cgroup(&sess.run_command(CGROUP_COMMAND)?));
+        if let Some(container) = limits.container {
+            table.add_row(vec!["Container".to_string(), format!("{} (free and top show the machine it runs on)", container)]);
+        }
+    }
+
+    for cmd in commands {
+        let output = sess.run_command(cmd)?;
+        let stats = parse_system_stats(&output);
+        if cmd == "uptime" && min_uptime.is_some_and(|window| stats.booted_within(window)) {
+            table.add_highlighted_row(vec![cmd.to_string(), format!("{} (recent reboot)", output.trim())]);
+        } else {
+            table.add_row(vec![cmd.to_string(), output.trim().to_string()]);
+        }
+    }
+    // `df -h` sizes are rounded, so the total comes from exact byte counts
+    let disks = parse_system_stats(&sess.run_command("df -B1")?);
+    if let Some(line) = disk_totals_line(&disks) {
+        table.add_row(vec!["Disk total".to_string(), line]);
+    }
+    if !disks.stale_mounts.is_empty() {
+        table.add_highlighted_row(vec!["Stale mounts".to_string(), disks.stale_mounts.join(", ")]);
+        for mount in &disks.stale_mounts {
+            warn(&format!("{} is stale or unreachable", mount));
+        }
+    }
+
+    Ok(table.render(format))
+}
+
+pub fn copy_to_clipboard(text: &str) -> Result<(), arboard::Error> {
+    arboard::Clipboard::new()?.set_text(text)
+}
+
+// Falls back to stdout on headless machines and over SSH, where there is no clipboard to reach
+pub fn copy_or_print(text: &str, quiet: bool) {
+    match copy_to_clipboard(text) {
+        Ok(()) if !quiet => eprintln!("Copied to clipboard"),
+        Ok(()) => {}
+        Err(e) => {
+            warn(&format!("Clipboard unavailable ({}), printing instead", e));
+            println!("{}", text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::OS_RELEASE_COMMAND;
+    use crate::test_support::*;
+
+    #[test]
+    fn disk_json_lists_every_mount_with_its_percentage() {
+        let stats = healthy_stats();
+        let json = disk_usage_json(&stats);
+        assert_eq!(json.as_array().map(Vec::len), Some(2));
+        assert_eq!(json[1]["mount"], "/data");
+        assert_eq!(json[1]["total"], 200_000_000_000u64);
+        assert_eq!(json[1]["used"], 190_000_000_000u64);
+        assert_eq!(json[1]["percent"], 95.0);
+
+        let table = get_disk_table(&stats, None, OutputFormat::Markdown);
+        assert!(table.contains("| /data | 186.26 GiB | 176.95 GiB | 95% |"), "{}", table);
+        assert!(table.ends_with("|\n\nTotal: 223.52 GiB used of 279.40 GiB (80%) across 2 filesystems"), "{}", table);
+    }
+
+    #[test]
+    fn snapshot_diff_lists_only_what_changed() {
+        let stats = healthy_stats();
+        let json = serialize_report(&HostStatus { host: "web1", stats: Some(&stats), error: None }, DataFormat::Json).unwrap();
+        let mut before: Snapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(get_snapshot_diff(&before, &stats, OutputFormat::Markdown), "\nNo changes since the snapshot\n");
+
+        before.memory_used = 1_000_000_000;
+        before.load_average.0 = 0.3;
+        before.uptime_seconds = stats.uptime_seconds.map(|seconds| seconds + 86_400);
+        before.disk_usage.retain(|(mount, _, _)| mount != "/data");
+        before.disk_usage.push(("/old".to_string(), 1_073_741_824, 0));
+        let diff = get_snapshot_diff(&before, &stats, OutputFormat::Markdown);
+        assert!(diff.starts_with("\nChanges since the snapshot:\n| Metric | Before | Now | Change |"), "{}", diff);
+        assert!(diff.contains("| **Memory used** | **953.67 MiB** | **1.86 GiB** | **+953.67 MiB** |"), "{}", diff);
+        assert!(diff.contains("| Load (1m) | 0.30 | 0.52 | +0.22 |"), "{}", diff);
+        assert!(diff.contains("| **Disk /data** | **-** | **176.95 GiB of 186.26 GiB** | **new mount** |"), "{}", diff);
+        assert!(diff.contains("| **Disk /old** | **0 B of 1 GiB** | **-** | **gone** |"), "{}", diff);
+        assert!(diff.contains("| **rebooted** |"), "{}", diff);
+        assert!(!diff.contains("Disk / "), "{}", diff);
+
+        // A snapshot of a host that couldn't be reached has nothing to compare with
+        let path = std::env::temp_dir().join(format!("remote_management_snapshot_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"host":"web1","error":"timed out"}"#).unwrap();
+        let error = Snapshot::load(&path).err().unwrap().to_string();
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.ends_with("has no stats, the host failed when it was taken: timed out"), "{}", error);
+    }
+
+    #[test]
+    fn history_summary_skips_missing_values() {
+        let sample = |timestamp, cpu, load| StoredSample { timestamp, cpu, memory: Some(50.0), disk: None, load };
+        let samples = [sample(0, Some(10.0), None), sample(60, Some(30.0), None), sample(120, None, None)];
+        let report = get_history_report("web1", &samples, true, OutputFormat::Markdown);
+        assert!(report.starts_with("3 samples for web1 from "), "{}", report);
+        assert!(report.contains("| CPU % | 10.0 | 20.0 | 30.0 |"), "{}", report);
+        assert!(report.contains("| Load (1m) | n/a |"), "{}", report);
+        assert_eq!(get_history_report("web1", &[], true, OutputFormat::Table), "No samples stored for web1");
+    }
+
+    #[test]
+    fn memory_hogs_are_listed_with_their_rss() {
+        let responder = ScriptedResponder::default().with(
+            MEMORY_HOGS_COMMAND,
+            output("    PID USER       RSS COMMAND\n   812 mysql   2097152 mysqld\n   301 root      51200 systemd-journal\n"),
+        );
+        let report = get_memory_hogs(&responder, OutputFormat::Markdown).unwrap();
+        assert!(report.contains("| 812 | mysql | 2 GiB | mysqld |"), "{}", report);
+        assert!(report.contains("| 301 | root | 50 MiB | systemd-journal |"), "{}", report);
+
+        let busybox = ScriptedResponder::default().with(MEMORY_HOGS_COMMAND, CommandOutput {
+            stdout: String::new(),
+            stderr: "ps: unrecognized option: sort=-rss\n".to_string(),
+            exit_status: 0,
+        });
+        let report = get_memory_hogs(&busybox, OutputFormat::Table).unwrap();
+        assert!(report.contains("unavailable: ps: unrecognized option"), "{}", report);
+    }
+
+    #[test]
+    fn oom_events_are_parsed_from_dmesg_and_journalctl() {
+        let events = parse_oom_events(
+            "[Mon Oct 12 10:00:00 2026] Out of memory: Killed process 1234 (java) total-vm:4194304kB, anon-rss:2097152kB\n\
+             2026-10-12T10:05:00+0000 web1 kernel: Memory cgroup out of memory: Killed process 812 (php-fpm) total-vm:524288kB\n",
+        );
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].time.as_str(), events[0].pid.as_str(), events[0].process.as_str()), ("Mon Oct 12 10:00:00 2026", "1234", "java"));
+        assert_eq!((events[1].time.as_str(), events[1].pid.as_str(), events[1].process.as_str()), ("2026-10-12T10:05:00+0000", "812", "php-fpm"));
+
+        let report = get_oom_events(&ScriptedResponder::default().with(OOM_EVENTS_COMMAND, output("")), OutputFormat::Table).unwrap();
+        assert!(report.contains("None in the kernel log"), "{}", report);
+    }
+
+    #[test]
+    fn pending_updates_use_the_distro_package_manager_and_count_security_fixes() {
+        let apt = "\
+Listing...
+openssl/jammy-updates,jammy-security 3.0.2-0ubuntu1.15 amd64 [upgradable from: 3.0.2-0ubuntu1.14]
+vim/jammy-updates 2:8.2.3995-1ubuntu2.16 amd64 [upgradable from: 2:8.2.3995-1ubuntu2.15]
+";
+        let ubuntu = healthy_host().with(APT_UPDATES_COMMAND, output(apt));
+        assert_eq!(pending_updates(&ubuntu).unwrap(), Some(PendingUpdates { total: 2, security: 1 }));
+
+        let dnf = "
+kernel.x86_64                 5.14.0-362.24.1.el9_3        baseos
+openssl.x86_64                1:3.0.7-25.el9_3             baseos
+vim-minimal.x86_64            2:8.2.2637-20.el9_1          baseos
+Obsoleting Packages
+grub2-tools.x86_64            1:2.06-70.el9_3.2            baseos
+---
+RLSA-2024:1234 Important/Sec. kernel-5.14.0-362.24.1.el9_3.x86_64
+RLSA-2024:1250 Important/Sec. openssl-1:3.0.7-25.el9_3.x86_64
+RLSA-2024:1301 Moderate/Sec.  openssl-1:3.0.7-25.el9_3.x86_64
+";
+        let rocky = ScriptedResponder::default()
+            .with(OS_RELEASE_COMMAND, output("NAME=\"Rocky Linux\"\nID=\"rocky\"\nID_LIKE=\"rhel centos fedora\"\n"))
+            .with(DNF_UPDATES_COMMAND, output(dnf));
+        assert_eq!(pending_updates(&rocky).unwrap(), Some(PendingUpdates { total: 3, security: 2 }));
+
+        let alpine = ScriptedResponder::default().with(OS_RELEASE_COMMAND, output("NAME=\"Alpine Linux\"\nID=alpine\n"));
+        assert_eq!(pending_updates(&alpine).unwrap(), None);
+
+        let results = vec![
+            ("web1".to_string(), Ok(Some(PendingUpdates { total: 2, security: 1 }))),
+            ("db1".to_string(), Ok(Some(PendingUpdates { total: 0, security: 0 }))),
+            ("edge".to_string(), Ok(None)),
+        ];
+        let table = get_updates_table(&results, OutputFormat::Markdown);
+        assert!(table.contains("| **web1** | **2** | **1** |"), "{}", table);
+        assert!(table.contains("| db1 | 0 | 0 |"), "{}", table);
+        assert!(table.contains("| edge | n/a (no apt, dnf or yum) |  |"), "{}", table);
+        assert!(table.ends_with("1 of 3 hosts have pending security updates"), "{}", table);
+    }
+
+    #[test]
+    fn host_status_serializes_stats_next_to_the_host() {
+        let stats = healthy_stats();
+        let results = [("web1".to_string(), Ok(stats)), ("db1".to_string(), Err(anyhow::anyhow!("refused")))];
+        let hosts: Vec<HostStatus> = results.iter().map(HostStatus::from).collect();
+
+        let json: serde_json::Value = serde_json::from_str(&serialize_report(&hosts, DataFormat::Json).unwrap()).unwrap();
+        assert_eq!(json[0]["host"], "web1");
+        assert_eq!(json[0]["memory_total"], 8_000_000_000u64);
+        assert_eq!(json[0]["error"], serde_json::Value::Null);
+        assert_eq!(json[1]["error"], "refused");
+
+        let yaml = serialize_report(&hosts, DataFormat::Yaml).unwrap();
+        assert!(yaml.starts_with("- host: web1\n"), "{}", yaml);
+        assert!(yaml.contains("\n  memory_total: 8000000000\n"), "{}", yaml);
+        assert!(yaml.ends_with("- host: db1\n  error: refused"), "{}", yaml);
+    }
+
+    #[test]
+    fn fleet_sorts_by_metric_with_failures_first() {
+        let host = |name: &str, cpu: f64| {
+            let mut stats = healthy_stats();
+            stats.cpu_usage = cpu;
+            (name.to_string(), Ok(stats))
+        };
+        let mut results = vec![host("web1", 10.0), host("web2", 80.0), ("db1".to_string(), Err(anyhow::anyhow!("refused"))), host("web3", 80.0)];
+        let names = |results: &[(String, Result<SystemStats>)]| results.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>();
+
+        sort_fleet(&mut results, HostSort::Cpu);
+        assert_eq!(names(&results), ["db1", "web2", "web3", "web1"]);
+        sort_fleet(&mut results, HostSort::Name);
+        assert_eq!(names(&results), ["db1", "web1", "web2", "web3"]);
+    }
+
+    #[test]
+    fn raw_output_includes_stderr_and_exit_status() {
+        let responder = healthy_host().with(
+            "free -b",
+            CommandOutput {
+                stdout: String::new(),
+                stderr: "free: command not found\n".to_string(),
+                exit_status: 127,
+            },
+        );
+        let report = get_raw_output(&responder).unwrap();
+        assert!(report.contains("$ free -b\n--- stderr ---\nfree: command not found\n--- exit status 127 ---"));
+        assert!(report.contains("$ uptime\n"));
+    }
+
+    #[test]
+    fn markdown_tables_escape_cells_and_pad_short_rows() {
+        let mut table = ReportTable::new(&["Host", "CPU", "Memory"]);
+        table.add_row(vec!["web1".to_string(), "a|b".to_string(), "line 1\nline 2\n".to_string()]);
+        table.add_highlighted_row(vec!["web2".to_string(), "Error: refused".to_string()]);
+        assert_eq!(
+            table.render(OutputFormat::Markdown),
+            "| Host | CPU | Memory |\n\
+             | --- | --- | --- |\n\
+             | web1 | a\\|b | line 1<br>line 2 |\n\
+             | **web2** | **Error: refused** |  |\n"
+        );
+    }
+
+    #[test]
+    fn table_styles_change_only_the_borders() {
+        let mut table = ReportTable::new(&["Metric", "Value"]);
+        table.add_row(vec!["uptime".to_string(), "up 5 days".to_string()]);
+        let default = table.render_styled(OutputFormat::Table, TableStyle::Default);
+        assert!(default.starts_with("+--------+-----------+\n| Metric | Value     |\n+"), "{}", default);
+
+        let clean = table.render_styled(OutputFormat::Table, TableStyle::Clean);
+        assert!(!clean.contains('|') && !clean.contains('+'), "{}", clean);
+        assert!(clean.contains("uptime") && clean.contains("up 5 days"));
+
+        let markdown = table.render_styled(OutputFormat::Table, TableStyle::Markdown);
+        assert_eq!(markdown, table.render(OutputFormat::Markdown));
+    }
+
+    #[test]
+    fn wide_tables_give_each_row_a_full_width_section() {
+        let mut table = ReportTable::new(&["Metric", "Value"]);
+        table.add_row(vec!["uptime".to_string(), " 10:00:00 up 5 days\n".to_string()]);
+        table.add_highlighted_row(vec!["df -h".to_string(), "Filesystem Size\n/dev/sda1  100G".to_string()]);
+        assert_eq!(
+            table.render_sections(20),
+            "── uptime ──────────\n 10:00:00 up 5 days\n\n\
+             ━━ df -h ━━━━━━━━━━━\nFilesystem Size\n/dev/sda1  100G\n\n"
+        );
+    }
+
+    #[test]
+    fn missing_metrics_and_stale_mounts_are_warned_about() {
+        let mut degraded = healthy_stats();
+        degraded.unavailable.push("load");
+        degraded.stale_mounts.push("/mnt/nfs".to_string());
+        warn_degraded("web1", &degraded);
+        assert!(warned());
+    }
+
+    #[test]
+    fn du_lists_the_largest_directories_against_the_total() {
+        let responder = ScriptedResponder::default().with(
+            &du_command("/var", 1, 2),
+            output("4194304\t/var\n3145728\t/var/lib\n1048576\t/var/log\n"),
+        );
+        let report = get_du_table(&responder, "/var", 1, 2, OutputFormat::Markdown).unwrap();
+        assert!(report.starts_with("4 GiB in /var\n| Size | % of /var | Directory |"), "{}", report);
+        assert!(report.contains("| 3 GiB | 75% | /var/lib |\n| 1 GiB | 25% | /var/log |\n"), "{}", report);
+
+        let empty = ScriptedResponder::default().with(&du_command("/missing", 1, 10), output(""));
+        assert_eq!(get_du_table(&empty, "/missing", 1, 10, OutputFormat::Table).unwrap(), "Nothing readable under /missing");
+    }
+
+    #[test]
+    fn log_sources_rank_files_and_journal_units() {
+        let responder = ScriptedResponder::default().with(
+            &log_sources_command("24 hours ago", 2),
+            output(
+                "### total\n2097152\t/var/log\n\
+                 ### files\n1572864\t/var/log/journal\n262144\t/var/log/syslog\n\
+                 ### journal\nArchived and active journals take up 1.5G in the file system.\n\
+                 ### units\n734003200\t1200000\tapp.service\n1024\t12\tkernel\n",
+            ),
+        );
+        let report = get_log_sources_report(&responder, "24 hours ago", 2, OutputFormat::Markdown).unwrap();
+        assert!(report.starts_with("2 GiB in /var/log\n| Size | % of /var/log | Path |"), "{}", report);
+        assert!(report.contains("| 1.50 GiB | 75% | /var/log/journal |\n| 256 MiB | 12% | /var/log/syslog |"), "{}", report);
+        assert!(report.contains("\n1.50 GiB of journal files\nLogged since 24 hours ago"), "{}", report);
+        assert!(report.contains("| 700 MiB | 1200000 | app.service |\n| 1 KiB | 12 | kernel |"), "{}", report);
+
+        assert_eq!(parse_journal_usage("Journals take up 8.0M on disk."), Some(8 * 1024 * 1024));
+        assert_eq!(parse_journal_usage("Archived and active journals take up 512B in the file system."), Some(512));
+        assert_eq!(parse_log_sources("### total\n### files\n### journal\n### units\n"), LogSources::default());
+    }
+
+    const PS_AUX: &str = "\
+USER         PID %CPU %MEM    VSZ   RSS TTY      STAT START   TIME COMMAND
+root           1  0.0  0.1 167000 11000 ?        Ss   Oct12   0:05 /sbin/init splash
+www-data     812  7.5  2.0 300000 160000 ?       S    Oct12  12:01 nginx: worker process
+postgres     901  1.2  9.8 900000 800000 ?       Ss   Oct12   3:10 postgres: checkpointer
+";
+
+    #[test]
+    fn ps_aux_parses_commands_with_spaces() {
+        let processes = parse_ps_aux(PS_AUX);
+        assert_eq!(processes.len(), 3);
+        assert_eq!(processes[0].command, "/sbin/init splash");
+        assert_eq!(processes[1].user, "www-data");
+        assert_eq!(processes[2].rss_kib, 800_000);
+    }
+
+    #[test]
+    fn process_table_filters_and_sorts() {
+        let responder = ScriptedResponder::default().with("ps aux", output(PS_AUX));
+        let table = get_process_table(&responder, Some("nginx"), None, ProcessSort::Cpu, None, OutputFormat::Markdown).unwrap();
+        assert_eq!(table.lines().count(), 3);
+        assert!(table.contains("nginx: worker process"));
+
+        let table = get_process_table(&responder, None, None, ProcessSort::Mem, None, OutputFormat::Markdown).unwrap();
+        let pids: Vec<&str> = table.lines().skip(2).map(|l| l.split(" | ").next().unwrap()).collect();
+        assert_eq!(pids, ["| 901", "| 812", "| 1"]);
+
+        let table = get_process_table(&responder, None, Some("root"), ProcessSort::Pid, None, OutputFormat::Markdown).unwrap();
+        assert_eq!(table.lines().count(), 3);
+
+        // The cap applies after sorting, so the biggest stay
+        let table = get_process_table(&responder, None, None, ProcessSort::Mem, Some(1), OutputFormat::Markdown).unwrap();
+        assert!(table.contains("| 901 |"), "{}", table);
+        assert!(table.ends_with("| ... and 2 more |  |  |  |  |  |\n"), "{}", table);
+    }
+
+    #[test]
+    fn journal_entries_stream_as_syslog_lines() {
+        let entry = r#"{"__REALTIME_TIMESTAMP":"1700000000000000","PRIORITY":"3","SYSLOG_IDENTIFIER":"nginx","_PID":"812","MESSAGE":"upstream timed out"}"#;
+        let binary = r#"{"__REALTIME_TIMESTAMP":"1700000000000000","PRIORITY":"4","_COMM":"kernel","MESSAGE":[104,105]}"#;
+        let mut out = JournalWriter { inner: Vec::new(), pending: Vec::new(), color: false };
+        // Split mid-line the way channel reads arrive
+        let stream = format!("{}\n{}\n", entry, binary);
+        let (first, second) = stream.split_at(40);
+        out.write_all(first.as_bytes()).unwrap();
+        out.write_all(second.as_bytes()).unwrap();
+
+        let text = String::from_utf8(out.inner).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" nginx[812]: upstream timed out"), "{}", lines[0]);
+        assert!(lines[1].ends_with(" kernel: [binary message]"), "{}", lines[1]);
+    }
+
+    #[test]
+    fn redaction_gives_each_host_one_placeholder() {
+        let redaction = Redaction::new(false);
+        assert_eq!(redaction.add_host(&["admin@web1:2022", "web1", "web1.example.com"]), "host-1");
+        assert_eq!(redaction.add_host(&["db1", "db1", "10.0.0.7"]), "host-2");
+        // Seen again under a name it already has
+        assert_eq!(redaction.add_host(&["web1"]), "host-1");
+
+        assert_eq!(
+            redaction.apply("Connecting to web1.example.com (web1) and db1; web10 and web1-backup are other hosts"),
+            "Connecting to host-1 (host-1) and host-2; web10 and web1-backup are other hosts"
+        );
+        assert_eq!(redaction.apply("admin@web1:2022 -> 10.0.0.7:22"), "host-1 -> host-2:22");
+        // Without --redact-ips other addresses stay
+        assert_eq!(redaction.apply("from 192.168.1.5"), "from 192.168.1.5");
+    }
+
+    #[test]
+    fn redaction_scrubs_addresses_but_not_lookalikes() {
+        let redaction = Redaction::new(true);
+        assert_eq!(
+            redaction.apply("inet 192.168.1.5/24 brd 192.168.1.255, peer 10.0.0.1:22."),
+            "inet [ip]/24 brd [ip], peer [ip]:22."
+        );
+        assert_eq!(redaction.apply("inet6 fe80::1c2b:3aff:fe4d:5e6f/64 and ::1"), "inet6 [ip]/64 and [ip]");
+        assert_eq!(
+            redaction.apply("at 10:00:00 node deadbeef v1.2.3 ran 3 tasks"),
+            "at 10:00:00 node deadbeef v1.2.3 ran 3 tasks"
+        );
+    }
+
+    #[test]
+    fn journal_command_quotes_user_input() {
+        assert_eq!(
+            journal_command("err", Some("1 hour ago"), Some("nginx.service"), Some(50)),
+            "journalctl --no-pager -o json -p 'err' --since '1 hour ago' -u 'nginx.service' -n 50"
+        );
+    }
+
+    #[test]
+    fn spinner_shows_the_command_being_run() {
+        let spinner = ProgressBar::hidden();
+        let sess = WithSpinner { inner: &healthy_host(), spinner: &spinner };
+        let stats = crate::stats::gather_stats(&sess).unwrap();
+        assert_eq!(stats.cpu_count, 4);
+        assert_eq!(spinner.message(), "Detecting the distribution");
+        assert_eq!(sess.run_command("nproc").unwrap(), NPROC);
+        assert_eq!(spinner.message(), "Running nproc");
+    }
+
+    #[test]
+    fn utc_timestamps_are_rfc3339() {
+        let time = chrono::DateTime::from_timestamp(1_760_263_200, 0).unwrap();
+        assert_eq!(format_time_in(time, "%H:%M:%S", true), "2025-10-12T10:00:00Z");
+        let local = time.with_timezone(&chrono::Local).format("%H:%M:%S").to_string();
+        assert_eq!(format_time_in(time, "%H:%M:%S", false), local);
+    }
+
+    #[test]
+    fn crontabs_are_parsed_from_every_source() {
+        let output = "\
+### file /etc/crontab
+SHELL=/bin/sh
+# m h dom mon dow user  command
+17 *\t* * *\troot    cd / && run-parts --report /etc/cron.hourly
+### file /etc/cron.d/certbot
+@reboot root  certbot -q renew
+### dir daily
+logrotate
+man-db.dpkg-old
+### unprivileged
+### user deploy
+MAILTO=ops@example.com
+*/5 * * * * /srv/app/bin/sync  --quiet
+";
+        let (jobs, unprivileged) = parse_crontabs(output);
+        assert!(unprivileged);
+        let rows: Vec<_> = jobs.iter().map(|j| (j.schedule.as_str(), j.user.as_str(), j.command.as_str(), j.source.as_str())).collect();
+        assert_eq!(rows, [
+            ("17 * * * *", "root", "cd / && run-parts --report /etc/cron.hourly", "/etc/crontab"),
+            ("@reboot", "root", "certbot -q renew", "/etc/cron.d/certbot"),
+            ("@daily", "root", "/etc/cron.daily/logrotate", "/etc/cron.daily"),
+            ("*/5 * * * *", "deploy", "/srv/app/bin/sync  --quiet", "crontab -l"),
+        ]);
+
+        // A truncated line has no command to list
+        assert!(parse_crontabs("### user root\n* * * *\n").0.is_empty());
+    }
+
+    #[test]
+    fn parse_last_skips_pseudo_entries() {
+        let output = "\
+alice    pts/0        203.0.113.7      Mon Oct 12 10:00   still logged in
+reboot   system boot  0.0.0.0          Mon Oct 12 09:58   still running
+
+wtmp begins Thu Oct  1 00:00:01 2026
+";
+        let entries = parse_last(output);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].user, "alice");
+        assert_eq!(entries[0].source, "203.0.113.7");
+        assert_eq!(entries[0].time, "Mon Oct 12 10:00 still logged in");
+    }
+
+    #[test]
+    fn exec_report_groups_output_and_marks_failures() {
+        let output = |stdout: &str, exit_status| CommandOutput { stdout: stdout.to_string(), stderr: String::new(), exit_status };
+        let results = vec![
+            ("web1".to_string(), Ok(output("5.15.0\n", 0))),
+            ("web2".to_string(), Ok(output("", 127))),
+            ("web3".to_string(), Err(anyhow::anyhow!("connection refused"))),
+        ];
+
+        let text = get_exec_report(&results, ExecFormat::Text);
+        assert!(text.starts_with("=== web1 ===\n5.15.0\n\n=== web2 FAILED (exit status 127) ===\n"), "{}", text);
+        assert!(text.contains("=== web3 FAILED ===\nError: connection refused\n"), "{}", text);
+        assert!(text.ends_with("2 of 3 hosts failed"), "{}", text);
+
+        let json: serde_json::Value = serde_json::from_str(&get_exec_report(&results, ExecFormat::Json)).unwrap();
+        assert_eq!(json[0]["stdout"], "5.15.0\n");
+        assert_eq!(json[1]["exit_status"], 127);
+        assert_eq!(json[2]["error"], "connection refused");
+    }
+}
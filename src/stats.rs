@@ -0,0 +1,1228 @@
+use crate::ssh::{CommandOutput, RemoteExec, SshError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// One sample of a host's resource usage. Memory and disk figures are in bytes.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SystemStats {
+    pub cpu_usage: f64,
+    pub cpu_steal: f64, // percent of time the hypervisor ran someone else while this VM wanted the CPU
+    pub memory_total: u64,
+    pub memory_used: u64,
+    pub swap_total: u64,
+    pub swap_used: u64,
+    pub swap_pages: Option<(u64, u64)>, // pages swapped (in, out) since boot
+    pub swap_rate: Option<(f64, f64)>,  // pages swapped (in, out) per second since the previous sample
+    pub oom_kills: Option<u64>,         // processes the OOM killer has killed since boot
+    pub tcp_established: Option<u64>,   // TCP connections in the ESTABLISHED state
+    #[serde(skip)]
+    pub last_oom_kill: Option<Instant>, // when a sample last saw that count go up
+    pub disk_usage: Vec<(String, u64, u64)>, // (mount point, total, used)
+    #[serde(skip)]
+    pub disk_devices: Vec<(String, String)>, // (mount point, device) for the mounts df named a device for
+    pub stale_mounts: Vec<String>, // mounts df listed without sizes, like a stale NFS handle; not empty disks
+    pub readonly_mounts: Vec<ReadonlyMount>, // writable filesystems the kernel has made read-only
+    pub load_average: (f64, f64, f64),
+    pub cpu_count: usize,
+    pub uptime: String,
+    pub uptime_seconds: Option<u64>,
+    pub os: Option<OsRelease>,
+    pub kernel: Option<KernelMetrics>, // only sampled when asked for
+    pub container: Option<Container>,  // set when the host is a container, whose cgroup limits replace the machine's
+    pub unavailable: Vec<&'static str>, // metrics that neither the command nor its fallback produced, so their zeros mean "no data"
+    #[serde(skip)]
+    pub sampled_at: Option<Instant>,
+}
+
+/// The distribution a host runs, from the os-release file.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OsRelease {
+    pub name: String,               // NAME, e.g. "Ubuntu"
+    pub version_id: Option<String>, // VERSION_ID, e.g. "22.04"; rolling releases have none
+    pub id: Option<String>,         // ID, e.g. "ubuntu"
+    pub id_like: Vec<String>,       // ID_LIKE, the distributions this one derives from
+}
+
+impl OsRelease {
+    /// The package manager of this distribution or the one it derives from, for commands
+    /// that differ between e.g. Debian and Fedora.
+    pub fn package_manager(&self) -> Option<&'static str> {
+        self.id.iter().chain(&self.id_like).find_map(|id| match id.as_str() {
+            "debian" | "ubuntu" => Some("apt"),
+            "fedora" | "rhel" | "centos" => Some("dnf"),
+            "alpine" => Some("apk"),
+            "arch" => Some("pacman"),
+            "suse" | "opensuse" => Some("zypper"),
+            _ => None,
+        })
+    }
+}
+
+impl std::fmt::Display for OsRelease {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version_id {
+            Some(version) => write!(f, "{} {}", self.name, version),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+/// Parses an os-release file: KEY=value lines whose values may be quoted.
+pub fn parse_os_release(output: &str) -> Option<OsRelease> {
+    let mut os = OsRelease::default();
+    for line in output.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let value = value.trim_matches(|c| c == '"' || c == '\'').to_string();
+        match key {
+            "NAME" => os.name = value,
+            "VERSION_ID" => os.version_id = Some(value),
+            "ID" => os.id = Some(value),
+            "ID_LIKE" => os.id_like = value.split_whitespace().map(str::to_string).collect(),
+            _ => {}
+        }
+    }
+    (!os.name.is_empty()).then_some(os)
+}
+
+/// Prints the host's os-release file; systems without /etc/os-release may still ship the copy in /usr/lib.
+pub const OS_RELEASE_COMMAND: &str = "cat /etc/os-release 2>/dev/null || cat /usr/lib/os-release 2>/dev/null";
+
+/// Reads the host's distribution. Prefer [`RemoteExec::os_release`], which a connection caches.
+pub fn read_os_release(sess: &(impl RemoteExec + ?Sized)) -> Result<Option<OsRelease>, SshError> {
+    let output = sess.run_command(OS_RELEASE_COMMAND)?;
+    Ok(parse_os_release(&output))
+}
+
+/// Scheduler counters that explain slowness the CPU and memory figures don't show: a host
+/// thrashing between tasks, flooded with interrupts or with a long run queue.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct KernelMetrics {
+    pub context_switches: u64, // since boot
+    pub interrupts: u64,       // since boot
+    pub runnable: u64,         // tasks running or waiting for a CPU
+    pub tasks: u64,            // all processes and threads
+    pub blocked: u64,          // tasks waiting on I/O
+    pub rates: Option<(f64, f64)>, // (context switches, interrupts) per second since the previous sample
+}
+
+/// Prints the counters [`parse_kernel_metrics`] reads. The `intr` line lists every IRQ after the
+/// total, so only the total is kept.
+pub const KERNEL_METRICS_COMMAND: &str =
+    "awk '/^(ctxt|intr|procs_blocked) /{print $1, $2}' /proc/stat; cat /proc/loadavg";
+
+// "ctxt N", "intr N" and "procs_blocked N" from /proc/stat, then /proc/loadavg, whose fourth field
+// is runnable/total tasks
+pub fn parse_kernel_metrics(output: &str) -> Option<KernelMetrics> {
+    let counter = |name: &str| {
+        output
+            .lines()
+            .find_map(|l| l.strip_prefix(name))
+            .and_then(|v| v.trim().parse::<u64>().ok())
+    };
+    let (runnable, tasks) = output
+        .lines()
+        .filter_map(|l| l.split_whitespace().nth(3)?.split_once('/'))
+        .find_map(|(runnable, tasks)| Some((runnable.parse().ok()?, tasks.parse().ok()?)))?;
+    Some(KernelMetrics {
+        context_switches: counter("ctxt ")?,
+        interrupts: counter("intr ")?,
+        runnable,
+        tasks,
+        blocked: counter("procs_blocked ").unwrap_or(0),
+        rates: None,
+    })
+}
+
+pub fn read_kernel_metrics(sess: &impl RemoteExec) -> Result<Option<KernelMetrics>, SshError> {
+    Ok(parse_kernel_metrics(&sess.run_command(KERNEL_METRICS_COMMAND)?))
+}
+
+/// Inside a container `free`, `nproc` and `top` describe the machine it runs on, so a
+/// containerized host's memory, cores and CPU usage come from its cgroup instead.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Container {
+    pub runtime: String,           // "docker", "podman", "kubernetes", "lxc", ...
+    pub memory_limit: Option<u64>, // memory.max, when it is below the machine's memory
+    pub cpu_limit: Option<f64>,    // cores the CPU quota allows, when it is below the machine's
+    #[serde(skip)]
+    pub cpu_time: Option<u64>, // microseconds of CPU the cgroup has used, for its own CPU usage
+}
+
+// "docker, 512 MiB, 1.5 cores", leaving out the limits that aren't set
+impl std::fmt::Display for Container {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.runtime)?;
+        if let Some(limit) = self.memory_limit {
+            write!(f, ", {}", humansize::format_size(limit, humansize::BINARY))?;
+        }
+        if let Some(cores) = self.cpu_limit {
+            write!(f, ", {} cores", (cores * 100.0).round() / 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the markers [`parse_container_runtime`] looks for: the files Docker and Podman
+/// leave in their containers, systemd's record of the container manager, and PID 1's cgroup.
+pub const CONTAINER_COMMAND: &str = "test -e /.dockerenv && echo docker; test -e /run/.containerenv && echo podman; \
+     cat /run/systemd/container 2>/dev/null; echo; cat /proc/1/cgroup 2>/dev/null";
+
+/// The container runtime a host runs under, or None on a VM or bare metal. Kubernetes wins over
+/// the runtime underneath it, since its pods are what the limits are set on.
+pub fn parse_container_runtime(output: &str) -> Option<String> {
+    let (cgroups, markers): (Vec<&str>, Vec<&str>) =
+        output.lines().map(str::trim).filter(|l| !l.is_empty()).partition(|l| l.contains(':'));
+    // "12:memory:/docker/<id>" with cgroup v1; under v2 PID 1 usually just sees "0::/"
+    let from_cgroup = |patterns: &[&str]| cgroups.iter().any(|l| patterns.iter().any(|p| l.contains(p)));
+    if from_cgroup(&["kubepods"]) {
+        return Some("kubernetes".to_string());
+    }
+    if let Some(marker) = markers.first() {
+        return Some(marker.to_string());
+    }
+    [("docker", &["/docker", "docker-"][..]), ("podman", &["libpod"]), ("lxc", &["/lxc", "lxc.payload"]), ("containerd", &["containerd"])]
+        .into_iter()
+        .find(|(_, patterns)| from_cgroup(patterns))
+        .map(|(runtime, _)| runtime.to_string())
+}
+
+/// Reads the host's container runtime. Prefer [`RemoteExec::container_runtime`], which a
+/// connection caches.
+pub fn read_container_runtime(sess: &(impl RemoteExec + ?Sized)) -> Result<Option<String>, SshError> {
+    Ok(parse_container_runtime(&sess.run_command(CONTAINER_COMMAND)?))
+}
+
+/// Prints "<file> <contents>" for the cgroup v2 files [`parse_cgroup`] reads, or their cgroup v1
+/// equivalents, from the container's own cgroup. Page cache that can be dropped (inactive_file)
+/// is left out of the memory used, like `docker stats` does.
+pub const CGROUP_COMMAND: &str = "cd /sys/fs/cgroup 2>/dev/null && for f in memory.max memory.current cpu.max \
+     memory/memory.limit_in_bytes memory/memory.usage_in_bytes cpu/cpu.cfs_quota_us cpu/cpu.cfs_period_us \
+     cpuacct/cpuacct.usage; do [ -r $f ] && echo \"$f $(cat $f)\"; done; \
+     grep -h '^inactive_file ' memory.stat memory/memory.stat 2>/dev/null; grep -h '^usage_usec ' cpu.stat 2>/dev/null; true";
+
+/// What a cgroup allows and has used, from [`CGROUP_COMMAND`]. Unlimited resources are None.
+#[derive(Debug, Default, PartialEq)]
+pub struct CgroupUsage {
+    pub memory_limit: Option<u64>,
+    pub memory_used: Option<u64>,
+    pub cpu_limit: Option<f64>, // cores
+    pub cpu_time: Option<u64>,  // microseconds
+}
+
+pub fn parse_cgroup(output: &str) -> CgroupUsage {
+    let value = |name: &str| output.lines().find_map(|l| l.strip_prefix(name)?.strip_prefix(' ')).map(str::trim);
+    let number = |name: &str| value(name).and_then(|v| v.parse::<u64>().ok());
+    // cgroup v1 writes "no limit" as a huge page-aligned number rather than "max"
+    let limit = |bytes: u64| Some(bytes).filter(|&bytes| bytes < 1 << 62);
+    let inactive = number("inactive_file").unwrap_or(0);
+
+    let memory_limit = number("memory.max").or_else(|| number("memory/memory.limit_in_bytes")).and_then(limit);
+    let memory_used = number("memory.current")
+        .or_else(|| number("memory/memory.usage_in_bytes"))
+        .map(|used| used.saturating_sub(inactive));
+    // "cpu.max" is "<quota> <period>" or "max <period>"; v1's quota is -1 when unlimited
+    let quota = value("cpu.max").and_then(|v| v.split_once(' ')).or_else(|| Some((value("cpu/cpu.cfs_quota_us")?, value("cpu/cpu.cfs_period_us")?)));
+    let cpu_limit = quota.and_then(|(quota, period)| match (quota.parse::<u64>(), period.parse::<u64>()) {
+        (Ok(quota), Ok(period)) if period > 0 => Some(quota as f64 / period as f64),
+        _ => None,
+    });
+    let cpu_time = number("usage_usec").or_else(|| number("cpuacct/cpuacct.usage").map(|nanos| nanos / 1000));
+    CgroupUsage { memory_limit, memory_used, cpu_limit, cpu_time }
+}
+
+/// Prints the mount table and then, after a "### fstab" marker, /etc/fstab. mountinfo rather than
+/// /proc/mounts, since it tells a mount's own options (a read-only bind mount) apart from the
+/// filesystem's (the whole filesystem read-only, which is what errors=remount-ro leaves behind).
+pub const MOUNTS_COMMAND: &str = "cat /proc/self/mountinfo; echo '### fstab'; cat /etc/fstab 2>/dev/null";
+
+// Filesystems that are mounted read-write unless asked otherwise. squashfs, iso9660, erofs and
+// the like can't be written at all, so being read-only means nothing there.
+const WRITABLE_FILESYSTEMS: [&str; 15] =
+    ["ext2", "ext3", "ext4", "xfs", "btrfs", "f2fs", "jfs", "reiserfs", "zfs", "vfat", "exfat", "ntfs3", "nfs", "nfs4", "cifs"];
+
+/// A normally writable filesystem that is read-only without /etc/fstab asking for it, most often
+/// because the kernel remounted it after I/O errors.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReadonlyMount {
+    pub mount: String,
+    pub device: String,
+    pub fstype: String,
+}
+
+// mountinfo and fstab write a space in a path as \040 (and a tab as \011)
+fn unescape_mount(path: &str) -> String {
+    path.replace("\\040", " ").replace("\\011", "\t")
+}
+
+/// Parses [`MOUNTS_COMMAND`]'s output. mountinfo lines are "<id> <parent> <dev> <root> <mount>
+/// <mount options> [optional fields] - <fstype> <source> <superblock options>".
+pub fn parse_readonly_mounts(output: &str) -> Vec<ReadonlyMount> {
+    let (mountinfo, fstab) = output.split_once("### fstab\n").unwrap_or((output, ""));
+    let meant_readonly: Vec<String> = fstab
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let options = fields.get(3)?;
+            options.split(',').any(|option| option == "ro").then(|| unescape_mount(fields[1]))
+        })
+        .collect();
+
+    let mut mounts: Vec<ReadonlyMount> = Vec::new();
+    for line in mountinfo.lines() {
+        let Some((mount, filesystem)) = line.split_once(" - ") else {
+            continue;
+        };
+        let filesystem: Vec<&str> = filesystem.split_whitespace().collect();
+        let (Some(mount), [fstype, device, options, ..]) = (mount.split_whitespace().nth(4), filesystem.as_slice()) else {
+            continue;
+        };
+        let mount = unescape_mount(mount);
+        if WRITABLE_FILESYSTEMS.contains(fstype)
+            && options.split(',').any(|option| option == "ro")
+            && !meant_readonly.contains(&mount)
+            && !mounts.iter().any(|known| known.mount == mount)
+        {
+            mounts.push(ReadonlyMount { mount, device: device.to_string(), fstype: fstype.to_string() });
+        }
+    }
+    mounts
+}
+
+/// Samples of CPU and load history kept when no other length is configured.
+pub const DEFAULT_HISTORY: usize = 100;
+
+fn push_history(history: &mut Vec<f64>, value: f64, limit: usize) {
+    if history.is_empty() {
+        history.reserve_exact(limit);
+    }
+    history.push(value);
+    if history.len() > limit {
+        history.drain(..history.len() - limit);
+    }
+}
+
+/// The recent samples behind the monitor's charts and trend arrows. Lives for the whole session,
+/// apart from the per-sample [`SystemStats`], so neither a new sample nor a reconnect resets a graph.
+#[derive(Debug, Default, Clone)]
+pub struct MetricHistory {
+    pub limit: usize,  // samples kept per chart
+    pub interval: u64, // seconds between samples
+    pub cpu: Vec<f64>,
+    pub memory: Vec<f64>, // percent used
+    pub steal: Vec<f64>,
+    pub load: Vec<f64>, // 1-minute load
+    pub connections: Vec<f64>, // established TCP connections
+    pub disk: HashMap<String, Vec<u64>>, // used bytes per mount point
+}
+
+impl MetricHistory {
+    pub fn new(limit: usize, interval: u64) -> Self {
+        MetricHistory { limit, interval, ..Default::default() }
+    }
+
+    /// Appends a sample to every history, keeping at most `limit` samples per chart.
+    pub fn record(&mut self, stats: &SystemStats) {
+        push_history(&mut self.cpu, stats.cpu_usage, self.limit);
+        if stats.memory_total > 0 {
+            push_history(&mut self.memory, stats.memory_percent(), self.limit);
+        }
+        push_history(&mut self.steal, stats.cpu_steal, self.limit);
+        push_history(&mut self.load, stats.load_average.0, self.limit);
+        if let Some(connections) = stats.tcp_established {
+            push_history(&mut self.connections, connections as f64, self.limit);
+        }
+
+        const MAX_DISK_HISTORY: usize = 10;
+        // Drop mounts that have disappeared so the map doesn't grow forever
+        let mounts: Vec<&String> = stats.disk_usage.iter().map(|(mount, _, _)| mount).collect();
+        self.disk.retain(|mount, _| mounts.contains(&mount));
+        for (mount, _, used) in &stats.disk_usage {
+            let history = self.disk.entry(mount.clone()).or_default();
+            if history.len() >= MAX_DISK_HISTORY {
+                history.remove(0);
+            }
+            history.push(*used);
+        }
+    }
+
+    /// Whether each of the last few samples had steal time: a noisy neighbour or a throttled
+    /// instance rather than a one-off blip.
+    pub fn steal_persistent(&self) -> bool {
+        const SAMPLES: usize = 5;
+        self.steal.len() >= SAMPLES && self.steal.iter().rev().take(SAMPLES).all(|&steal| steal > 0.0)
+    }
+
+    // Arrow showing whether a mount has been filling up or draining over the recent samples.
+    // Changes under 0.1% of the filesystem are treated as flat.
+    pub fn disk_trend(&self, mount: &str, total: u64) -> &'static str {
+        let Some(history) = self.disk.get(mount) else {
+            return "→";
+        };
+        let (Some(first), Some(last)) = (history.first(), history.last()) else {
+            return "→";
+        };
+        let delta = *last as f64 - *first as f64;
+        if delta.abs() < total as f64 * 0.001 {
+            "→"
+        } else if delta > 0.0 {
+            "↑"
+        } else {
+            "↓"
+        }
+    }
+}
+
+impl SystemStats {
+    /// Turns the swap counters of `previous` and this sample into a per-second rate. Swap
+    /// activity shows memory pressure that a modest swap-used figure hides.
+    pub fn update_swap_rate(&mut self, previous: &SystemStats) {
+        let (Some((in_before, out_before)), Some((in_now, out_now))) = (previous.swap_pages, self.swap_pages) else {
+            return;
+        };
+        let (Some(before), Some(now)) = (previous.sampled_at, self.sampled_at) else {
+            return;
+        };
+        let seconds = now.duration_since(before).as_secs_f64();
+        if seconds > 0.0 {
+            self.swap_rate = Some((
+                in_now.saturating_sub(in_before) as f64 / seconds,
+                out_now.saturating_sub(out_before) as f64 / seconds,
+            ));
+        }
+    }
+
+    /// Turns the context switch and interrupt counters of `previous` and this sample into
+    /// per-second rates.
+    pub fn update_kernel_rates(&mut self, previous: &SystemStats) {
+        let (Some(before), Some(now)) = (&previous.kernel, &mut self.kernel) else {
+            return;
+        };
+        let (Some(then), Some(at)) = (previous.sampled_at, self.sampled_at) else {
+            return;
+        };
+        let seconds = at.duration_since(then).as_secs_f64();
+        if seconds > 0.0 {
+            now.rates = Some((
+                now.context_switches.saturating_sub(before.context_switches) as f64 / seconds,
+                now.interrupts.saturating_sub(before.interrupts) as f64 / seconds,
+            ));
+        }
+    }
+
+    /// Replaces the machine's memory and core count with what the container's cgroup allows,
+    /// where that is less. CPU usage needs two samples, see [`SystemStats::update_container_cpu`].
+    pub fn apply_cgroup(&mut self, runtime: String, cgroup: &CgroupUsage) {
+        let mut container = Container { runtime, cpu_time: cgroup.cpu_time, ..Container::default() };
+        if let (Some(limit), Some(used)) = (cgroup.memory_limit, cgroup.memory_used) {
+            if self.memory_total == 0 || limit < self.memory_total {
+                (self.memory_total, self.memory_used) = (limit, used.min(limit));
+                container.memory_limit = Some(limit);
+            }
+        }
+        if let Some(cores) = cgroup.cpu_limit.filter(|&cores| self.cpu_count == 0 || cores < self.cpu_count as f64) {
+            self.cpu_count = (cores.ceil() as usize).max(1);
+            container.cpu_limit = Some(cores);
+        }
+        self.container = Some(container);
+    }
+
+    /// Turns the cgroup's CPU time of `previous` and this sample into the container's own CPU
+    /// usage, as a share of the cores it may use; `top` only knows the machine's.
+    pub fn update_container_cpu(&mut self, previous: &SystemStats) {
+        let (Some(before), Some(now)) = (&previous.container, &self.container) else {
+            return;
+        };
+        let (Some(used_before), Some(used_now)) = (before.cpu_time, now.cpu_time) else {
+            return;
+        };
+        let (Some(then), Some(at)) = (previous.sampled_at, self.sampled_at) else {
+            return;
+        };
+        let cores = now.cpu_limit.unwrap_or(self.cpu_count as f64);
+        let seconds = at.duration_since(then).as_secs_f64();
+        if seconds > 0.0 && cores > 0.0 {
+            let usage = used_now.saturating_sub(used_before) as f64 / 1e6 / seconds / cores * 100.0;
+            self.cpu_usage = usage.min(100.0);
+        }
+    }
+
+    /// Remembers when the OOM killer's count last went up, carrying over what `previous` knew.
+    pub fn update_oom_kills(&mut self, previous: &SystemStats) {
+        self.last_oom_kill = match (previous.oom_kills, self.oom_kills) {
+            (Some(before), Some(now)) if now > before => self.sampled_at,
+            _ => previous.last_oom_kill,
+        };
+    }
+
+    pub fn available(&self, metric: &str) -> bool {
+        !self.unavailable.contains(&metric)
+    }
+
+    /// Whether the host booted less than `window` ago, e.g. after an unexpected crash or OOM reboot.
+    pub fn booted_within(&self, window: Duration) -> bool {
+        self.uptime_seconds.is_some_and(|seconds| seconds < window.as_secs())
+    }
+
+    pub fn memory_percent(&self) -> f64 {
+        self.memory_used as f64 / self.memory_total as f64 * 100.0
+    }
+
+    // The mount with the highest usage percentage
+    pub fn fullest_disk(&self) -> Option<(&str, f64)> {
+        self.disk_usage
+            .iter()
+            .filter(|(_, total, _)| *total > 0)
+            .map(|(mount, total, used)| (mount.as_str(), *used as f64 / *total as f64 * 100.0))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// The `max` fullest mounts, still in df's order, and how many were left out; every mount
+    /// without a `max`. Mounts of equal usage keep their df order.
+    pub fn fullest_disks(&self, max: Option<usize>) -> (Vec<&(String, u64, u64)>, usize) {
+        let max = max.unwrap_or(usize::MAX);
+        if self.disk_usage.len() <= max {
+            return (self.disk_usage.iter().collect(), 0);
+        }
+        let percent = |(_, total, used): &(String, u64, u64)| if *total > 0 { *used as f64 / *total as f64 } else { 0.0 };
+        let mut order: Vec<usize> = (0..self.disk_usage.len()).collect();
+        order.sort_by(|&a, &b| percent(&self.disk_usage[b]).total_cmp(&percent(&self.disk_usage[a])));
+        order.truncate(max);
+        order.sort();
+        (order.into_iter().map(|i| &self.disk_usage[i]).collect(), self.disk_usage.len() - max)
+    }
+
+    /// (total, used, filesystems) across every disk, for capacity reports. Only block devices
+    /// are in `disk_usage`, which leaves out tmpfs and the like; loop devices are left out here
+    /// too, as they're mostly snap packages' squashfs images, always full. A bind mount is of
+    /// the same device as the filesystem it repeats, so each device counts once.
+    pub fn disk_totals(&self) -> Option<(u64, u64, usize)> {
+        let mut seen: Vec<&str> = Vec::new();
+        let (mut total, mut used) = (0, 0);
+        for (mount, mount_total, mount_used) in &self.disk_usage {
+            // A mount read without its device (the statfs fallback) can only count as its own
+            let device = self.disk_devices.iter().find(|(m, _)| m == mount).map_or(mount.as_str(), |(_, device)| device);
+            if *mount_total == 0 || device.starts_with("/dev/loop") || seen.contains(&device) {
+                continue;
+            }
+            seen.push(device);
+            total += mount_total;
+            used += mount_used;
+        }
+        (!seen.is_empty()).then_some((total, used, seen.len()))
+    }
+}
+
+/// Parses the concatenated output of [`STATS_COMMANDS`].
+pub fn parse_system_stats(output: &str) -> SystemStats {
+    let mut stats = SystemStats::default();
+    let (mut found_cpu, mut found_memory, mut found_load) = (false, false, false);
+    
+    // Parse CPU usage from top
+    if let Some(cpu_line) = output.lines().find(|l| l.contains("%Cpu(s)")) {
+        let parts: Vec<&str> = cpu_line.split_whitespace().collect();
+        // Look for the "us," (user CPU usage) value
+        for (i, part) in parts.iter().enumerate() {
+            if *part == "us," && i > 0 {
+                if let Ok(user_cpu) = parts[i - 1].parse::<f64>() {
+                    // User CPU percentage + System CPU percentage (if available)
+                    stats.cpu_usage = user_cpu;
+                    found_cpu = true;
+                    // Try to find system CPU usage
+                    if let Some(sys_idx) = parts.iter().position(|p| *p == "sy,") {
+                        if let Ok(sys_cpu) = parts[sys_idx - 1].parse::<f64>() {
+                            stats.cpu_usage += sys_cpu;
+                        }
+                    }
+                    // Steal is the last field, so it has no trailing comma
+                    if let Some(st_idx) = parts.iter().position(|p| p.trim_end_matches(',') == "st") {
+                        stats.cpu_steal = parts[st_idx - 1].parse().unwrap_or(0.0);
+                    }
+                    break;
+                }
+            }
+        }
+    }
+    
+    // Parse memory usage from free
+    for line in output.lines() {
+        if line.starts_with("Mem:") {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() >= 3 {
+                stats.memory_total = parts[1].parse().unwrap_or(0);
+                stats.memory_used = parts[2].parse().unwrap_or(0);
+                found_memory = stats.memory_total > 0;
+            }
+        } else if line.starts_with("Swap:") {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() >= 3 {
+                stats.swap_total = parts[1].parse().unwrap_or(0);
+                stats.swap_used = parts[2].parse().unwrap_or(0);
+            }
+        }
+    }
+
+    // Parse load average from uptime
+    if let Some(uptime_line) = output.lines().find(|l| l.contains("load average:")) {
+        if let Some(load_str) = uptime_line.split("load average:").nth(1) {
+            let loads: Vec<f64> = load_str
+                .split(',')
+                .filter_map(|s| s.trim().parse().ok())
+                .collect();
+            if loads.len() >= 3 {
+                stats.load_average = (loads[0], loads[1], loads[2]);
+                found_load = true;
+            }
+        }
+        stats.uptime = uptime_line.to_string();
+        stats.uptime_seconds = parse_uptime_seconds(uptime_line);
+    }
+
+    // Parse the core count from nproc, the only command that prints a bare number
+    if let Some(count) = output.lines().find_map(|l| l.trim().parse::<usize>().ok()) {
+        stats.cpu_count = count;
+    }
+
+    // Parse swap activity from vmstat -s
+    let counter = |suffix: &str| {
+        output
+            .lines()
+            .find(|l| l.trim_end().ends_with(suffix))
+            .and_then(|l| l.split_whitespace().next())
+            .and_then(|v| v.parse::<u64>().ok())
+    };
+    if let (Some(swapped_in), Some(swapped_out)) = (counter("pages swapped in"), counter("pages swapped out")) {
+        stats.swap_pages = Some((swapped_in, swapped_out));
+    }
+    parse_oom_kills(output, &mut stats);
+
+    // Parse established connections from ss -s: "TCP:   14 (estab 5, closed 2, ...)"
+    stats.tcp_established = output
+        .lines()
+        .find(|l| l.starts_with("TCP:"))
+        .and_then(|l| l.split_once("estab "))
+        .and_then(|(_, rest)| rest.split(|c: char| !c.is_ascii_digit()).next())
+        .and_then(|v| v.parse().ok());
+
+    // Parse disk usage from df. Only block devices count towards usage, but a network share
+    // ("server:/export") whose sizes df couldn't read is listed as stale along with them.
+    for line in output.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let [device, total, used, _, _, mount, ..] = parts.as_slice() else {
+            continue;
+        };
+        match (total.parse::<u64>(), used.parse::<u64>()) {
+            (Ok(total), Ok(used)) if device.starts_with('/') => {
+                stats.disk_usage.push((mount.to_string(), total, used));
+                stats.disk_devices.push((mount.to_string(), device.to_string()));
+            }
+            (Ok(_), Ok(_)) => {}
+            _ if device.starts_with('/') || device.contains(":/") => stats.stale_mounts.push(mount.to_string()),
+            _ => {}
+        }
+    }
+
+    // Empty or garbled output leaves a metric at zero, which must not pass for an idle host
+    let found = [
+        ("cpu", found_cpu),
+        ("memory", found_memory),
+        ("disk", !stats.disk_usage.is_empty() || !stats.stale_mounts.is_empty()),
+        ("load", found_load),
+        ("cores", stats.cpu_count > 0),
+        ("swap", stats.swap_pages.is_some()),
+        ("oom", stats.oom_kills.is_some()),
+        ("connections", stats.tcp_established.is_some()),
+    ];
+    stats.unavailable = found.into_iter().filter(|(_, found)| !found).map(|(metric, _)| metric).collect();
+    stats
+}
+
+// Reads the "up ..." part of an uptime line, which comes in forms like "up 5 days,  2:03,",
+// "up 10 min," or "up 1 day, 3 min,", followed by the user count and load average
+fn parse_uptime_seconds(line: &str) -> Option<u64> {
+    let (_, rest) = line.split_once(" up ")?;
+    let mut seconds = 0;
+    let mut found = false;
+    for part in rest.split(',').map(str::trim) {
+        if part.contains("user") || part.contains("load average") {
+            break;
+        }
+        let mut words = part.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some(days), Some("day" | "days")) => seconds += days.parse::<u64>().ok()? * 86400,
+            (Some(minutes), Some("min" | "mins")) => seconds += minutes.parse::<u64>().ok()? * 60,
+            (Some(clock), None) => {
+                let (hours, minutes) = clock.split_once(':')?;
+                seconds += hours.parse::<u64>().ok()? * 3600 + minutes.parse::<u64>().ok()? * 60;
+            }
+            _ => return None,
+        }
+        found = true;
+    }
+    found.then_some(seconds)
+}
+
+/// The commands whose output [`parse_system_stats`] understands.
+pub const STATS_COMMANDS: [&str; 8] = [
+    "top -bn1 | head -n 20", // Get more lines from top to ensure we capture CPU info
+    "free -b",
+    "df -B1",
+    "uptime",
+    "nproc",
+    "vmstat -s",
+    "grep ^oom_kill /proc/vmstat", // empty before Linux 4.13, which doesn't count OOM kills
+    "ss -s",
+];
+
+/// What to run when a [`STATS_COMMANDS`] entry isn't installed, in the same order. The fallbacks
+/// only read /proc (plus `stat` for disks), which minimal containers still have.
+pub struct StatsFallback {
+    pub metric: &'static str,
+    pub command: &'static str,
+    pub parse: fn(&str, &mut SystemStats) -> bool,
+}
+
+pub const STATS_FALLBACKS: [StatsFallback; 8] = [
+    StatsFallback {
+        metric: "cpu",
+        command: "head -n 1 /proc/stat; sleep 1; head -n 1 /proc/stat",
+        parse: parse_proc_stat,
+    },
+    StatsFallback { metric: "memory", command: "cat /proc/meminfo", parse: parse_proc_meminfo },
+    StatsFallback {
+        metric: "disk",
+        command: "while read -r dev mnt rest; do case \"$dev\" in /*) echo \"$mnt $(stat -f -c '%S %b %f' \"$mnt\")\";; esac; done < /proc/mounts",
+        parse: parse_proc_mounts,
+    },
+    StatsFallback { metric: "load", command: "cat /proc/loadavg /proc/uptime", parse: parse_proc_loadavg },
+    StatsFallback { metric: "cores", command: "grep -c ^processor /proc/cpuinfo", parse: parse_cpu_count },
+    StatsFallback { metric: "swap", command: "grep ^pswp /proc/vmstat", parse: parse_proc_vmstat },
+    StatsFallback { metric: "oom", command: "cat /proc/vmstat", parse: parse_oom_kills },
+    StatsFallback { metric: "connections", command: "cat /proc/net/tcp /proc/net/tcp6 2>/dev/null", parse: parse_proc_net_tcp },
+];
+
+// A shell reports a missing binary with exit status 127, but inside a pipeline only the
+// last command's status survives, so stderr is checked as well
+fn command_missing(output: &CommandOutput) -> bool {
+    output.exit_status == 127
+        || output.stderr.contains("command not found")
+        || output.stderr.contains(": not found")
+}
+
+// Two `cpu` lines from /proc/stat taken a second apart
+fn parse_proc_stat(output: &str, stats: &mut SystemStats) -> bool {
+    let samples: Vec<Vec<u64>> = output
+        .lines()
+        .filter(|l| l.starts_with("cpu "))
+        .map(|l| l.split_whitespace().skip(1).filter_map(|v| v.parse().ok()).collect())
+        .collect();
+    let [before, after] = samples.as_slice() else {
+        return false;
+    };
+    if before.len() < 4 || after.len() < 4 {
+        return false;
+    }
+    // idle + iowait count as idle time, like top does
+    let idle = |sample: &Vec<u64>| sample[3] + sample.get(4).copied().unwrap_or(0);
+    let total = after.iter().sum::<u64>().saturating_sub(before.iter().sum());
+    let busy = total.saturating_sub(idle(after).saturating_sub(idle(before)));
+    if total == 0 {
+        return false;
+    }
+    stats.cpu_usage = busy as f64 / total as f64 * 100.0;
+    if let (Some(before), Some(after)) = (before.get(7), after.get(7)) {
+        stats.cpu_steal = after.saturating_sub(*before) as f64 / total as f64 * 100.0;
+    }
+    true
+}
+
+fn parse_proc_meminfo(output: &str, stats: &mut SystemStats) -> bool {
+    let field = |name: &str| {
+        output
+            .lines()
+            .find(|l| l.starts_with(name))
+            .and_then(|l| l.split_whitespace().nth(1))
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(|kib| kib * 1024)
+    };
+    let (Some(total), Some(available)) = (field("MemTotal:"), field("MemAvailable:")) else {
+        return false;
+    };
+    stats.memory_total = total;
+    stats.memory_used = total.saturating_sub(available);
+    if let (Some(swap_total), Some(swap_free)) = (field("SwapTotal:"), field("SwapFree:")) {
+        stats.swap_total = swap_total;
+        stats.swap_used = swap_total.saturating_sub(swap_free);
+    }
+    true
+}
+
+// "<mount> <block size> <blocks> <free blocks>" per mounted block device
+fn parse_proc_mounts(output: &str, stats: &mut SystemStats) -> bool {
+    for line in output.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let [mount, block_size, blocks, free] = parts.as_slice() else {
+            continue;
+        };
+        let (Ok(block_size), Ok(blocks), Ok(free)) =
+            (block_size.parse::<u64>(), blocks.parse::<u64>(), free.parse::<u64>())
+        else {
+            continue;
+        };
+        if blocks > 0 {
+            stats
+                .disk_usage
+                .push((mount.to_string(), blocks * block_size, blocks.saturating_sub(free) * block_size));
+        }
+    }
+    !stats.disk_usage.is_empty()
+}
+
+fn parse_proc_loadavg(output: &str, stats: &mut SystemStats) -> bool {
+    let mut lines = output.lines();
+    let loads: Vec<f64> = lines
+        .next()
+        .unwrap_or_default()
+        .split_whitespace()
+        .take(3)
+        .filter_map(|v| v.parse().ok())
+        .collect();
+    let [one, five, fifteen] = loads.as_slice() else {
+        return false;
+    };
+    stats.load_average = (*one, *five, *fifteen);
+
+    let seconds = lines
+        .next()
+        .and_then(|l| l.split_whitespace().next())
+        .and_then(|v| v.parse::<f64>().ok())
+        .unwrap_or(0.0) as u64;
+    stats.uptime_seconds = Some(seconds);
+    stats.uptime = format!(
+        "up {} days, {}:{:02}, load average: {:.2}, {:.2}, {:.2}",
+        seconds / 86400,
+        seconds % 86400 / 3600,
+        seconds % 3600 / 60,
+        one,
+        five,
+        fifteen
+    );
+    true
+}
+
+fn parse_cpu_count(output: &str, stats: &mut SystemStats) -> bool {
+    match output.trim().parse() {
+        Ok(count) if count > 0 => {
+            stats.cpu_count = count;
+            true
+        }
+        _ => false,
+    }
+}
+
+// "pswpin <pages>" and "pswpout <pages>"
+fn parse_proc_vmstat(output: &str, stats: &mut SystemStats) -> bool {
+    let counter = |name: &str| {
+        output
+            .lines()
+            .find_map(|l| l.strip_prefix(name))
+            .and_then(|v| v.trim().parse::<u64>().ok())
+    };
+    let (Some(swapped_in), Some(swapped_out)) = (counter("pswpin "), counter("pswpout ")) else {
+        return false;
+    };
+    stats.swap_pages = Some((swapped_in, swapped_out));
+    true
+}
+
+// The `oom_kill` counter from /proc/vmstat
+fn parse_oom_kills(output: &str, stats: &mut SystemStats) -> bool {
+    stats.oom_kills = output
+        .lines()
+        .find_map(|l| l.strip_prefix("oom_kill "))
+        .and_then(|v| v.trim().parse().ok());
+    stats.oom_kills.is_some()
+}
+
+// Socket tables from /proc/net/tcp{,6}, where state 01 is ESTABLISHED
+fn parse_proc_net_tcp(output: &str, stats: &mut SystemStats) -> bool {
+    // Each table starts with a header line, even when it has no sockets
+    if !output.contains("local_address") {
+        return false;
+    }
+    let established = output.lines().filter(|l| l.split_whitespace().nth(3) == Some("01")).count();
+    stats.tcp_established = Some(established as u64);
+    true
+}
+
+/// Runs the metric commands on `sess` and parses a [`SystemStats`] sample from them. A metric
+/// whose command is missing or printed nothing usable is read through its fallback instead, and
+/// listed in `unavailable` if that fails too.
+pub fn gather_stats(sess: &impl RemoteExec) -> Result<SystemStats, SshError> {
+    let mut output = String::new();
+    for cmd in STATS_COMMANDS {
+        let result = sess.run_command_output(cmd)?;
+        if !command_missing(&result) {
+            output.push_str(&result.stdout);
+        }
+    }
+
+    let mut stats = parse_system_stats(&output);
+    let unparsed = std::mem::take(&mut stats.unavailable);
+    for fallback in STATS_FALLBACKS.iter().filter(|fallback| unparsed.contains(&fallback.metric)) {
+        let result = sess.run_command_output(fallback.command)?;
+        if command_missing(&result) || !(fallback.parse)(&result.stdout, &mut stats) {
+            stats.unavailable.push(fallback.metric);
+        }
+    }
+    stats.readonly_mounts = parse_readonly_mounts(&sess.run_command(MOUNTS_COMMAND)?);
+    if let Some(runtime) = sess.container_runtime()? {
+        stats.apply_cgroup(runtime, &parse_cgroup(&sess.run_command(CGROUP_COMMAND)?));
+    }
+    stats.os = sess.os_release()?;
+    stats.sampled_at = Some(Instant::now());
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn gather_stats_parses_command_output() {
+        let stats = gather_stats(&healthy_host()).unwrap();
+        assert!((stats.cpu_usage - 15.6).abs() < 1e-9);
+        assert_eq!(stats.cpu_steal, 0.0);
+        assert_eq!(stats.memory_total, 8_000_000_000);
+        assert_eq!(stats.memory_used, 2_000_000_000);
+        assert_eq!(stats.swap_used, 500_000_000);
+        assert_eq!(stats.load_average, (0.52, 0.58, 0.59));
+        assert_eq!(stats.cpu_count, 4);
+        assert_eq!(stats.swap_pages, Some((120, 4500)));
+        assert_eq!(stats.oom_kills, Some(2));
+        assert_eq!(stats.tcp_established, Some(5));
+        assert_eq!(stats.os.as_ref().map(OsRelease::to_string).as_deref(), Some("Ubuntu 22.04"));
+        assert_eq!(stats.disk_usage.len(), 2);
+        assert_eq!(stats.fullest_disk().map(|(mount, _)| mount), Some("/data"));
+    }
+
+    #[test]
+    fn gather_stats_falls_back_to_proc_for_missing_commands() {
+        let responder = ScriptedResponder::default()
+            .with(STATS_COMMANDS[0], CommandOutput {
+                stdout: String::new(),
+                stderr: "sh: 1: top: not found\n".to_string(),
+                exit_status: 0,
+            })
+            .with(STATS_FALLBACKS[0].command, output("cpu  100 0 100 700 100 0 0 0\ncpu  150 0 150 850 150 0 0 30\n"))
+            .with(STATS_FALLBACKS[1].command, output("MemTotal: 8000 kB\nMemFree: 1000 kB\nMemAvailable: 6000 kB\n"))
+            .with(STATS_COMMANDS[2], output(DF))
+            .with(STATS_COMMANDS[3], output(UPTIME))
+            .with(STATS_COMMANDS[4], output(NPROC))
+            .with(STATS_FALLBACKS[5].command, output("pswpin 10\npswpout 20\n"))
+            .with(STATS_FALLBACKS[6].command, output("pswpin 10\npswpout 20\noom_kill 1\n"))
+            .with(STATS_FALLBACKS[7].command, output(PROC_NET_TCP));
+        let stats = gather_stats(&responder).unwrap();
+        assert!((stats.cpu_usage - 39.394).abs() < 0.01, "{}", stats.cpu_usage);
+        assert!((stats.cpu_steal - 9.091).abs() < 0.01, "{}", stats.cpu_steal);
+        assert_eq!(stats.memory_total, 8_192_000);
+        assert_eq!(stats.memory_used, 2_048_000);
+        assert_eq!(stats.swap_pages, Some((10, 20)));
+        assert_eq!(stats.oom_kills, Some(1));
+        assert_eq!(stats.tcp_established, Some(1));
+        assert!(stats.unavailable.is_empty());
+
+        // Neither `df` nor the /proc/mounts fallback answered, so disk is reported missing, not empty
+        let responder = healthy_host().with(STATS_COMMANDS[2], CommandOutput {
+            stdout: String::new(),
+            stderr: "sh: 1: df: not found\n".to_string(),
+            exit_status: 127,
+        });
+        let stats = gather_stats(&responder).unwrap();
+        assert_eq!(stats.unavailable, ["disk"]);
+        assert!(stats.available("cpu"));
+    }
+
+    #[test]
+    fn empty_or_garbled_output_is_no_data_rather_than_zero() {
+        // `top` answers but prints nothing usable, `free` prints nothing at all
+        let responder = healthy_host()
+            .with(STATS_COMMANDS[0], output("top: failed tty get\n"))
+            .with(STATS_COMMANDS[1], output(""))
+            .with(STATS_FALLBACKS[1].command, output("MemTotal: 8000 kB\nMemAvailable: 6000 kB\n"));
+        let stats = gather_stats(&responder).unwrap();
+        assert_eq!(stats.unavailable, ["cpu"]);
+        assert!(!stats.available("cpu"));
+        assert_eq!(stats.memory_total, 8_192_000);
+
+        let stats = parse_system_stats("");
+        assert_eq!(stats.unavailable, ["cpu", "memory", "disk", "load", "cores", "swap", "oom", "connections"]);
+    }
+
+    #[test]
+    fn gather_stats_surfaces_command_failures() {
+        let responder = ScriptedResponder { broken_channel: true, ..Default::default() };
+        let err = gather_stats(&responder).unwrap_err();
+        assert!(matches!(err, SshError::CommandFailed { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn uptime_parsing_handles_each_uptime_format() {
+        assert_eq!(parse_uptime_seconds(UPTIME), Some(5 * 86400 + 2 * 3600 + 3 * 60));
+        assert_eq!(parse_uptime_seconds(" 10:00:00 up 7 min,  0 users,  load average: 1.20, 0.40, 0.10"), Some(420));
+        assert_eq!(parse_uptime_seconds(" 10:00:00 up 1 day, 3 min,  2 users,  load average: 0.00, 0.00, 0.00"), Some(86580));
+        assert_eq!(parse_uptime_seconds("10:00:00 up 2:03, load average: 0.00, 0.00, 0.00"), Some(7380));
+        assert_eq!(parse_uptime_seconds("garbage"), None);
+
+        let stats = healthy_stats();
+        assert!(!stats.booted_within(Duration::from_secs(600)));
+        assert!(stats.booted_within(Duration::from_secs(6 * 86400)));
+    }
+
+    #[test]
+    fn history_keeps_the_configured_number_of_samples() {
+        let mut history = MetricHistory::new(4, 1);
+        let mut stats = SystemStats::default();
+        for sample in 0..10 {
+            stats.cpu_usage = sample as f64;
+            history.record(&stats);
+        }
+        assert_eq!(history.cpu, [6.0, 7.0, 8.0, 9.0]);
+        assert!(history.cpu.capacity() >= 4);
+        assert_eq!(history.load.len(), 4);
+    }
+
+    #[test]
+    fn steal_is_parsed_and_flagged_when_it_persists() {
+        let stats = parse_system_stats("%Cpu(s): 40.0 us,  5.0 sy,  0.0 ni, 40.0 id,  0.0 wa,  0.0 hi,  0.0 si, 15.0 st\n");
+        assert_eq!(stats.cpu_steal, 15.0);
+
+        let mut history = MetricHistory::new(100, 1);
+        let mut stats = SystemStats::default();
+        for steal in [0.0, 2.0, 3.0, 1.0, 4.0] {
+            stats.cpu_steal = steal;
+            history.record(&stats);
+        }
+        assert!(!history.steal_persistent());
+        stats.cpu_steal = 0.5;
+        history.record(&stats);
+        assert!(history.steal_persistent());
+    }
+
+    #[test]
+    fn swap_rate_is_the_counter_delta_per_second() {
+        let start = Instant::now();
+        let previous = SystemStats { swap_pages: Some((100, 1000)), sampled_at: Some(start), ..Default::default() };
+        let mut stats = SystemStats {
+            swap_pages: Some((100, 1500)),
+            sampled_at: Some(start + Duration::from_secs(2)),
+            ..Default::default()
+        };
+        stats.update_swap_rate(&previous);
+        assert_eq!(stats.swap_rate, Some((0.0, 250.0)));
+
+        // The first sample has nothing to compare against
+        let mut first = stats.clone();
+        first.swap_rate = None;
+        first.update_swap_rate(&SystemStats::default());
+        assert_eq!(first.swap_rate, None);
+    }
+
+    #[test]
+    fn oom_kills_are_remembered_until_the_next_one() {
+        let start = Instant::now();
+        let previous = SystemStats { oom_kills: Some(2), sampled_at: Some(start), ..Default::default() };
+        let mut stats = SystemStats { oom_kills: Some(3), sampled_at: Some(start + Duration::from_secs(1)), ..Default::default() };
+        stats.update_oom_kills(&previous);
+        assert_eq!(stats.last_oom_kill, stats.sampled_at);
+
+        let mut next = SystemStats { oom_kills: Some(3), sampled_at: Some(start + Duration::from_secs(2)), ..Default::default() };
+        next.update_oom_kills(&stats);
+        assert_eq!(next.last_oom_kill, stats.sampled_at);
+    }
+
+    #[test]
+    fn disk_trend_follows_used_bytes_across_samples() {
+        let mut history = MetricHistory::new(100, 1);
+        let mut stats = SystemStats::default();
+        for used in [50_000, 50_010, 60_000] {
+            stats.disk_usage = vec![("/".to_string(), 1_000_000, used)];
+            history.record(&stats);
+        }
+        assert_eq!(history.disk_trend("/", 1_000_000), "↑");
+
+        stats.disk_usage = vec![("/".to_string(), 1_000_000, 50_500)];
+        history.record(&stats);
+        assert_eq!(history.disk_trend("/", 1_000_000), "→");
+        assert_eq!(history.disk_trend("/missing", 1_000_000), "→");
+    }
+
+    #[test]
+    fn mounts_without_sizes_are_stale_not_empty() {
+        let stats = parse_system_stats(
+            "Filesystem 1B-blocks Used Available Use% Mounted on\n\
+             /dev/sda1 100000000000 50000000000 50000000000 50% /\n\
+             nas:/export - - - - /mnt/nas\n\
+             /dev/sdc1 - - - - /mnt/usb\n\
+             tmpfs 1000000 0 1000000 0% /run\n",
+        );
+        assert_eq!(stats.disk_usage, [("/".to_string(), 100_000_000_000, 50_000_000_000)]);
+        assert_eq!(stats.stale_mounts, ["/mnt/nas", "/mnt/usb"]);
+        assert!(stats.available("disk"));
+
+        let table = crate::report::get_disk_table(&stats, None, crate::cli::OutputFormat::Markdown);
+        assert!(table.contains("| **/mnt/nas** | **-** | **-** | **unavailable** |"), "{}", table);
+        let json = crate::report::disk_usage_json(&stats);
+        assert_eq!(json[2]["mount"], "/mnt/usb");
+        assert_eq!(json[2]["unavailable"], true);
+        assert!(json[2]["percent"].is_null());
+    }
+
+    #[test]
+    fn filesystems_the_kernel_made_read_only_are_found() {
+        let output = "\
+22 1 8:1 / / ro,relatime shared:1 - ext4 /dev/sda1 ro,errors=remount-ro\n\
+23 22 8:2 / /boot ro,relatime shared:2 - ext4 /dev/sda2 ro\n\
+24 22 8:17 /volumes/config /etc/app ro,relatime - ext4 /dev/sdb1 rw\n\
+25 22 7:0 / /snap/core/1 ro,nodev shared:3 - squashfs /dev/loop0 ro\n\
+26 22 8:33 / /mnt/my\\040data rw,relatime - xfs /dev/sdc1 ro,attr2\n\
+27 22 8:1 /home /home ro,relatime - ext4 /dev/sda1 ro,errors=remount-ro\n\
+### fstab\n\
+# <file system> <mount point> <type> <options> <dump> <pass>\n\
+UUID=1234 / ext4 errors=remount-ro 0 1\n\
+UUID=5678 /boot ext4 ro,nodev 0 2\n";
+        let mounts = parse_readonly_mounts(output);
+        let names: Vec<&str> = mounts.iter().map(|ro| ro.mount.as_str()).collect();
+        // /boot is read-only on purpose, /etc/app is a read-only bind of a writable filesystem,
+        // and squashfs never is writable
+        assert_eq!(names, ["/", "/mnt/my data", "/home"]);
+        assert_eq!(mounts[0], ReadonlyMount { mount: "/".to_string(), device: "/dev/sda1".to_string(), fstype: "ext4".to_string() });
+        assert!(parse_readonly_mounts("").is_empty());
+    }
+
+    #[test]
+    fn capped_disks_keep_the_fullest_in_df_order() {
+        let mut stats = SystemStats::default();
+        for (mount, used) in [("/", 50), ("/a", 90), ("/b", 10), ("/c", 95)] {
+            stats.disk_usage.push((mount.to_string(), 100, used));
+        }
+        let mounts = |max| {
+            let (disks, hidden) = stats.fullest_disks(max);
+            (disks.into_iter().map(|(mount, _, _)| mount.as_str()).collect::<Vec<_>>(), hidden)
+        };
+        assert_eq!(mounts(Some(2)), (vec!["/a", "/c"], 2));
+        assert_eq!(mounts(Some(4)), (vec!["/", "/a", "/b", "/c"], 0));
+        assert_eq!(mounts(None).1, 0);
+    }
+
+    #[test]
+    fn disk_totals_count_each_device_once() {
+        let stats = parse_system_stats(DF);
+        assert_eq!(stats.disk_totals(), Some((300_000_000_000, 240_000_000_000, 2)));
+
+        // A bind mount of /data and a snap's squashfs image don't add to the total, but a second
+        // disk that happens to be the same size as /data does
+        let df = format!(
+            "{}{}{}{}",
+            DF,
+            "/dev/sdb1      200000000000 190000000000  10000000000  95% /srv/data\n",
+            "/dev/loop3         58327040     58327040            0 100% /snap/core20/2318\n",
+            "/dev/sdc1      200000000000 190000000000  10000000000  95% /backup\n",
+        );
+        let stats = parse_system_stats(&df);
+        assert_eq!(stats.disk_usage.len(), 5);
+        assert_eq!(stats.disk_totals(), Some((500_000_000_000, 430_000_000_000, 3)));
+        assert_eq!(SystemStats::default().disk_totals(), None);
+    }
+
+    #[test]
+    fn kernel_metrics_are_parsed_and_turned_into_rates() {
+        let sample = |ctxt: u64, intr: u64| {
+            let output = format!("ctxt {}\nintr {}\nprocs_blocked 2\n0.52 0.58 0.59 3/512 4242\n", ctxt, intr);
+            parse_kernel_metrics(&output).unwrap()
+        };
+        let first = sample(1_000, 500);
+        assert_eq!((first.runnable, first.tasks, first.blocked), (3, 512, 2));
+        assert!(parse_kernel_metrics("0.52 0.58 0.59 3/512 4242\n").is_none());
+
+        let start = Instant::now();
+        let previous = SystemStats { kernel: Some(first), sampled_at: Some(start), ..Default::default() };
+        let mut stats = SystemStats {
+            kernel: Some(sample(21_000, 2_500)),
+            sampled_at: Some(start + Duration::from_secs(10)),
+            ..Default::default()
+        };
+        stats.update_kernel_rates(&previous);
+        assert_eq!(stats.kernel.and_then(|k| k.rates), Some((2_000.0, 200.0)));
+    }
+
+    #[test]
+    fn os_release_is_parsed_and_picks_the_package_manager() {
+        let ubuntu = parse_os_release(OS_RELEASE).unwrap();
+        assert_eq!(ubuntu.name, "Ubuntu");
+        assert_eq!(ubuntu.version_id.as_deref(), Some("22.04"));
+        assert_eq!(ubuntu.package_manager(), Some("apt"));
+
+        let rocky = parse_os_release("NAME='Rocky Linux'\nVERSION_ID='9.3'\nID=rocky\nID_LIKE=\"rhel centos fedora\"\n").unwrap();
+        assert_eq!(rocky.to_string(), "Rocky Linux 9.3");
+        assert_eq!(rocky.package_manager(), Some("dnf"));
+
+        let arch = parse_os_release("NAME=\"Arch Linux\"\nID=arch\n").unwrap();
+        assert_eq!(arch.to_string(), "Arch Linux");
+        assert_eq!(arch.package_manager(), Some("pacman"));
+
+        assert_eq!(parse_os_release(""), None);
+    }
+
+    #[test]
+    fn container_runtimes_are_detected_from_their_markers() {
+        assert_eq!(parse_container_runtime("docker\n\n0::/\n").as_deref(), Some("docker"));
+        assert_eq!(parse_container_runtime("\n12:memory:/docker/3f2a\n").as_deref(), Some("docker"));
+        assert_eq!(parse_container_runtime("lxc\n0::/\n").as_deref(), Some("lxc"));
+        assert_eq!(parse_container_runtime("docker\n\n0::/kubepods/burstable/pod1/3f2a\n").as_deref(), Some("kubernetes"));
+        // A systemd machine's own PID 1 is not in a container
+        assert_eq!(parse_container_runtime("\n0::/init.scope\n"), None);
+        assert_eq!(parse_container_runtime("\n"), None);
+    }
+
+    #[test]
+    fn cgroup_limits_replace_the_machine_figures() {
+        let v2 = parse_cgroup("memory.max 536870912\nmemory.current 300000000\ncpu.max 150000 100000\ninactive_file 100000000\nusage_usec 5000000\n");
+        assert_eq!(v2, CgroupUsage {
+            memory_limit: Some(536_870_912),
+            memory_used: Some(200_000_000),
+            cpu_limit: Some(1.5),
+            cpu_time: Some(5_000_000),
+        });
+        let unlimited = parse_cgroup("memory.max max\nmemory.current 300000000\ncpu.max max 100000\n");
+        assert_eq!((unlimited.memory_limit, unlimited.cpu_limit), (None, None));
+        let v1 = parse_cgroup(
+            "memory/memory.limit_in_bytes 9223372036854771712\nmemory/memory.usage_in_bytes 1000\ncpu/cpu.cfs_quota_us 200000\ncpu/cpu.cfs_period_us 100000\ncpuacct/cpuacct.usage 7000000\n",
+        );
+        assert_eq!((v1.memory_limit, v1.cpu_limit, v1.cpu_time), (None, Some(2.0), Some(7000)));
+
+        let responder = healthy_host()
+            .with(CONTAINER_COMMAND, output("docker\n\n0::/\n"))
+            .with(CGROUP_COMMAND, output("memory.max 536870912\nmemory.current 300000000\ncpu.max 150000 100000\nusage_usec 5000000\n"));
+        let first = gather_stats(&responder).unwrap();
+        assert_eq!((first.memory_total, first.memory_used, first.cpu_count), (536_870_912, 300_000_000, 2));
+        assert_eq!(first.container.as_ref().map(Container::to_string).as_deref(), Some("docker, 512 MiB, 1.5 cores"));
+
+        // The container's CPU usage replaces top's once there are two samples: 0.75s over 1s of 1.5 cores
+        let mut second = first.clone();
+        second.sampled_at = first.sampled_at.map(|at| at + Duration::from_secs(1));
+        second.container.as_mut().unwrap().cpu_time = Some(5_750_000);
+        second.update_container_cpu(&first);
+        assert!((second.cpu_usage - 50.0).abs() < 1e-9);
+
+        // Limits above what the machine has don't bind, so the machine's figures stay
+        let mut roomy = SystemStats { memory_total: 1000, cpu_count: 4, ..Default::default() };
+        roomy.apply_cgroup("lxc".to_string(), &CgroupUsage { memory_limit: Some(4000), memory_used: Some(10), cpu_limit: Some(8.0), cpu_time: None });
+        assert_eq!((roomy.memory_total, roomy.cpu_count), (1000, 4));
+        assert_eq!(roomy.container.map(|c| c.to_string()).as_deref(), Some("lxc"));
+    }
+}
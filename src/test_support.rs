@@ -0,0 +1,159 @@
+// Shared fixtures for the unit tests: a scripted TCP server for connection-level failures and
+// a scripted command responder for everything that runs on top of a session. Neither speaks
+// SSH, so authentication and SFTP uploads aren't covered; `exec_command` is tested against a
+// fake channel in the ssh tests instead.
+
+use crate::ssh::{CommandOutput, RemoteExec, SshError, Target};
+use crate::stats::{gather_stats, SystemStats, OS_RELEASE_COMMAND, STATS_COMMANDS};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::TcpListener;
+use std::thread;
+
+// A scripted stand-in for an SSH server: accepts a single connection, plays back
+// `reply` and hangs up. Returns the port it is listening on.
+pub(crate) fn spawn_mock_server(reply: &'static [u8]) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        if let Ok((mut stream, _)) = listener.accept() {
+            let _ = stream.write_all(reply);
+        }
+    });
+    port
+}
+
+pub(crate) fn local_target(port: u16) -> Target {
+    Target {
+        host: "127.0.0.1".to_string(),
+        username: Some("test".to_string()),
+        port,
+        shell: None,
+        command_prefix: None,
+        known_hosts: None,
+        proxy_command: None,
+        http_proxy: None,
+        unix_socket: None,
+        connect_timeout: None,
+        banner_timeout: None,
+        command_timeout: None,
+        cipher: None,
+        kex: None,
+        compress: false,
+        read_only: false,
+        password: None,
+        prompt_once: false,
+        identities: Vec::new(),
+    }
+}
+
+pub(crate) fn output(stdout: &str) -> CommandOutput {
+    CommandOutput {
+        stdout: stdout.to_string(),
+        stderr: String::new(),
+        exit_status: 0,
+    }
+}
+
+// Answers commands from a fixed script; unknown commands behave like a missing binary
+#[derive(Default)]
+pub(crate) struct ScriptedResponder {
+    pub(crate) responses: HashMap<String, CommandOutput>,
+    pub(crate) broken_channel: bool,
+    pub(crate) failures: Cell<usize>, // calls whose channel fails before the script answers again
+}
+
+impl ScriptedResponder {
+    pub(crate) fn with(mut self, command: &str, response: CommandOutput) -> Self {
+        self.responses.insert(command.to_string(), response);
+        self
+    }
+}
+
+impl RemoteExec for ScriptedResponder {
+    fn run_command_output(&self, command: &str) -> Result<CommandOutput, SshError> {
+        if self.broken_channel || self.failures.get() > 0 {
+            self.failures.set(self.failures.get().saturating_sub(1));
+            return Err(SshError::CommandFailed {
+                command: command.to_string(),
+                source: std::io::Error::new(std::io::ErrorKind::BrokenPipe, "channel closed"),
+            });
+        }
+        Ok(self.responses.get(command).map_or_else(
+            || CommandOutput {
+                stdout: String::new(),
+                stderr: format!("sh: 1: {}: not found\n", command),
+                exit_status: 127,
+            },
+            |response| CommandOutput {
+                stdout: response.stdout.clone(),
+                stderr: response.stderr.clone(),
+                exit_status: response.exit_status,
+            },
+        ))
+    }
+}
+
+pub(crate) const TOP: &str = "\
+top - 10:00:00 up 5 days,  2:03,  1 user,  load average: 0.52, 0.58, 0.59
+Tasks: 123 total,   1 running, 122 sleeping,   0 stopped,   0 zombie
+%Cpu(s): 12.5 us,  3.1 sy,  0.0 ni, 84.0 id,  0.2 wa,  0.0 hi,  0.2 si,  0.0 st
+";
+pub(crate) const FREE: &str = "\
+           total        used        free      shared  buff/cache   available
+Mem:      8000000000  2000000000  4000000000    10000000  2000000000  5800000000
+Swap:     2000000000   500000000  1500000000
+";
+pub(crate) const DF: &str = "\
+Filesystem        1B-blocks         Used    Available Use% Mounted on
+tmpfs             800000000      1000000    799000000   1% /run
+/dev/sda1      100000000000  50000000000  50000000000  50% /
+/dev/sdb1      200000000000 190000000000  10000000000  95% /data
+";
+pub(crate) const UPTIME: &str = " 10:00:00 up 5 days,  2:03,  1 user,  load average: 0.52, 0.58, 0.59\n";
+
+pub(crate) const OS_RELEASE: &str = "\
+PRETTY_NAME=\"Ubuntu 22.04.4 LTS\"
+NAME=\"Ubuntu\"
+VERSION_ID=\"22.04\"
+ID=ubuntu
+ID_LIKE=debian
+";
+
+pub(crate) const NPROC: &str = "4\n";
+
+pub(crate) const VMSTAT: &str = "\
+      8000000 K total memory
+          120 pages swapped in
+         4500 pages swapped out
+";
+
+pub(crate) const SS: &str = "\
+Total: 180
+TCP:   14 (estab 5, closed 2, orphaned 0, timewait 2)
+";
+
+pub(crate) const PROC_NET_TCP: &str = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:0016 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1234 1
+   1: 0100007F:0016 0100007F:D431 01 00000000:00000000 02:00061A80 00000000     0        0 5678 4
+";
+
+pub(crate) fn healthy_host() -> ScriptedResponder {
+    ScriptedResponder::default()
+        .with(STATS_COMMANDS[0], output(TOP))
+        .with(STATS_COMMANDS[1], output(FREE))
+        .with(STATS_COMMANDS[2], output(DF))
+        .with(STATS_COMMANDS[3], output(UPTIME))
+        .with(STATS_COMMANDS[4], output(NPROC))
+        .with(STATS_COMMANDS[5], output(VMSTAT))
+        .with(STATS_COMMANDS[6], output("oom_kill 2\n"))
+        .with(STATS_COMMANDS[7], output(SS))
+        .with(OS_RELEASE_COMMAND, output(OS_RELEASE))
+}
+
+// What `gather_stats` makes of `healthy_host`, for tests that start from a sample
+pub(crate) fn healthy_stats() -> SystemStats {
+    gather_stats(&healthy_host()).unwrap()
+}
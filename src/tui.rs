@@ -0,0 +1,1782 @@
+use crate::alerts::{check_thresholds, send_webhook_alert, Alert, AlertLog, Thresholds};
+use crate::cast::{Cast, Recorder};
+use crate::cli::{ChartMarker, OutputFormat, Units};
+use crate::db::{MetricsDb, StoredSample};
+use crate::report::{copy_to_clipboard, disk_totals_line, format_duration, format_time, format_timestamp, format_uptime, get_fleet_table, redact};
+use crate::ssh::{reconnect, take_slowest_command, RemoteExec, Remote, Retrying, SshError, Target, HOLD_TIMINGS};
+use crate::stats::{gather_stats, read_kernel_metrics, KernelMetrics, MetricHistory, SystemStats};
+use anyhow::{Context, Result};
+use crossterm::{
+    event::{self, Event, KeyCode},
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    ExecutableCommand,
+};
+use humansize::{format_size, BINARY};
+use ratatui::{
+    prelude::*,
+    text::{Line, Text},
+    widgets::*,
+    Terminal,
+};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// The dashboard's colors. Any of them can be overridden from a TOML theme file whose values are
+/// color names ("light-blue"), "#rrggbb" or a 0-255 palette index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Theme {
+    pub ok: Color,       // usage under 60%
+    pub warn: Color,     // usage from 60% to 85%, steal and swapping
+    pub critical: Color, // usage over 85%, alerts and errors
+    pub accent: Color,   // the CPU chart and the focused panel
+    pub muted: Color,    // help text, axes and hosts without data
+    pub reboot: Color,   // fleet cells of hosts that rebooted recently
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            ok: Color::Green,
+            warn: Color::Yellow,
+            critical: Color::Red,
+            accent: Color::Cyan,
+            muted: Color::Gray,
+            reboot: Color::Magenta,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    ok: Option<String>,
+    warn: Option<String>,
+    critical: Option<String>,
+    accent: Option<String>,
+    muted: Option<String>,
+    reboot: Option<String>,
+}
+
+impl Theme {
+    pub fn load(path: &Path) -> Result<Theme> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read theme {}", path.display()))?;
+        let file: ThemeFile = toml::from_str(&text).with_context(|| format!("Invalid theme {}", path.display()))?;
+        let mut theme = Theme::default();
+        for (key, value, color) in [
+            ("ok", file.ok, &mut theme.ok),
+            ("warn", file.warn, &mut theme.warn),
+            ("critical", file.critical, &mut theme.critical),
+            ("accent", file.accent, &mut theme.accent),
+            ("muted", file.muted, &mut theme.muted),
+            ("reboot", file.reboot, &mut theme.reboot),
+        ] {
+            if let Some(value) = value {
+                *color = value
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid theme {}: {} = {:?} is not a color", path.display(), key, value))?;
+            }
+        }
+        Ok(theme)
+    }
+
+    // Severity color for a usage percentage: ok below 60%, warn up to 85%, critical above
+    pub fn usage(&self, percent: f64) -> Color {
+        if percent > 85.0 {
+            self.critical
+        } else if percent >= 60.0 {
+            self.warn
+        } else {
+            self.ok
+        }
+    }
+
+    // The bands of `usage`, as a key that fits in a border
+    fn scale_key(&self) -> Line<'static> {
+        Line::from(vec![
+            Span::raw(" "),
+            Span::styled("■", Style::default().fg(self.ok)),
+            Span::raw(" under 60%  "),
+            Span::styled("■", Style::default().fg(self.warn)),
+            Span::raw(" 60–85%  "),
+            Span::styled("■", Style::default().fg(self.critical)),
+            Span::raw(" over 85% (load: of cores) "),
+        ])
+    }
+}
+
+/// Reloads a theme file whenever its modification time changes, so colors can be tuned while
+/// `monitor` runs.
+pub struct ThemeWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ThemeWatcher {
+    // Fails if the file can't be loaded now; later reload errors are returned by `poll`
+    pub fn new(path: PathBuf) -> Result<(ThemeWatcher, Theme)> {
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        let theme = Theme::load(&path)?;
+        Ok((ThemeWatcher { path, modified }, theme))
+    }
+
+    // The reloaded theme if the file changed since the last look. A file that is missing for a
+    // moment, as some editors save by replacing it, counts as unchanged.
+    pub fn poll(&mut self) -> Option<Result<Theme>> {
+        let modified = std::fs::metadata(&self.path).and_then(|m| m.modified()).ok()?;
+        if self.modified == Some(modified) {
+            return None;
+        }
+        self.modified = Some(modified);
+        Some(Theme::load(&self.path))
+    }
+}
+
+pub struct MonitorOptions {
+    pub host: String,
+    pub target: Target, // to reconnect to if the server closes the session
+    pub interval: u64,
+    pub thresholds: Thresholds,
+    pub alert_webhook: Option<String>,
+    pub alert_cooldown: Option<Duration>, // how soon a metric that fired may fire again
+    pub alert_log: Option<AlertLog>,
+    pub min_uptime: Option<Duration>,
+    pub history: usize,
+    pub db: Option<MetricsDb>,
+    pub marker: symbols::Marker,
+    pub color_scale: bool,
+    pub units: Units,
+    pub compact: bool,
+    pub retries: u32,
+    pub theme_file: Option<PathBuf>, // reloaded whenever it changes
+    pub kernel_metrics: bool,        // sample context switches, interrupts and the run queue too
+    pub max_disks: Option<usize>,
+    pub record: Option<PathBuf>, // asciicast file to save the dashboard's frames to
+}
+
+// Fails up front when stdout is a file or pipe, where raw mode and the alternate screen would
+// only write escape codes into it. With `record`, every frame drawn is also saved to that file.
+pub fn setup_terminal(record: Option<&Path>) -> Result<(Terminal<CrosstermBackend<Recorder<std::io::Stdout>>>, TerminalGuard)> {
+    use std::io::IsTerminal;
+    if !std::io::stdout().is_terminal() {
+        anyhow::bail!("This view needs a terminal, but stdout is redirected to a file or pipe");
+    }
+    // Created before raw mode, so a path that can't be written doesn't leave the terminal in it
+    let cast = record.map(|path| Cast::create(path, crossterm::terminal::size()?)).transpose()?;
+    HOLD_TIMINGS.store(true, Ordering::SeqCst);
+    enable_raw_mode()?;
+    let guard = TerminalGuard { restored: false };
+    std::io::stdout().execute(EnterAlternateScreen)?;
+    Ok((Terminal::new(CrosstermBackend::new(Recorder::new(std::io::stdout(), cast)))?, guard))
+}
+
+fn restore_terminal() -> Result<()> {
+    disable_raw_mode()?;
+    std::io::stdout().execute(LeaveAlternateScreen)?;
+    HOLD_TIMINGS.store(false, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Puts the terminal back the way [`setup_terminal`] found it when dropped, so a view that ends
+/// early on an error (or a panic) doesn't leave the shell in raw mode inside the alternate screen
+/// with the error drawn where nobody sees it. Views leaving normally call [`TerminalGuard::restore`],
+/// which reports a failure to restore.
+#[must_use]
+pub struct TerminalGuard {
+    restored: bool,
+}
+
+impl TerminalGuard {
+    pub fn restore(mut self) -> Result<()> {
+        self.restored = true;
+        restore_terminal()
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        if !self.restored {
+            let _ = restore_terminal();
+        }
+    }
+}
+
+// Dashboard panels that can take the keyboard focus, in Tab order
+#[derive(Clone, Copy, PartialEq)]
+pub enum Panel {
+    Cpu,
+    Load,
+    Connections,
+    Memory,
+    Disks,
+}
+
+const PANELS: [Panel; 5] = [Panel::Cpu, Panel::Load, Panel::Connections, Panel::Memory, Panel::Disks];
+
+// The history chart that `g` has picked to fill the chart row on its own
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChartMetric {
+    Cpu,
+    Memory,
+    Load,
+    Connections,
+}
+
+impl ChartMetric {
+    // All charts side by side (None), then each on its own, then back
+    fn next(chart: Option<ChartMetric>) -> Option<ChartMetric> {
+        match chart {
+            None => Some(ChartMetric::Cpu),
+            Some(ChartMetric::Cpu) => Some(ChartMetric::Memory),
+            Some(ChartMetric::Memory) => Some(ChartMetric::Load),
+            Some(ChartMetric::Load) => Some(ChartMetric::Connections),
+            Some(ChartMetric::Connections) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum DiskSort {
+    #[default]
+    Mount, // in the order df lists them
+    Usage, // fullest first
+}
+
+// Which dashboard panel has the focus, plus the scroll and sort state of the panels that have any.
+// Only the focused panel reacts to scroll and sort keys.
+#[derive(Clone, Copy)]
+pub struct DashboardView {
+    pub focus: usize,
+    pub disk_scroll: usize,
+    pub disk_sort: DiskSort,
+    pub marker: symbols::Marker, // how the charts draw their lines
+    pub color_scale: bool,       // show what the usage colors mean
+    pub units: Units,            // how the memory bars and disk list show usage
+    pub compact: bool,           // gauges in place of the history charts
+    pub chart: Option<ChartMetric>, // None shows every chart
+    pub max_disks: Option<usize>,   // only list the fullest this many mounts
+    pub theme: Theme,
+}
+
+impl DashboardView {
+    pub fn new(marker: symbols::Marker, color_scale: bool, units: Units, compact: bool) -> Self {
+        DashboardView {
+            focus: 0,
+            disk_scroll: 0,
+            disk_sort: DiskSort::default(),
+            marker,
+            color_scale,
+            units,
+            compact,
+            chart: None,
+            max_disks: None,
+            theme: Theme::default(),
+        }
+    }
+
+    pub fn focused(&self) -> Panel {
+        PANELS[self.focus]
+    }
+
+    // Returns false if the key means nothing to the dashboard, so the caller can handle it
+    pub fn handle_key(&mut self, code: KeyCode, stats: &SystemStats) -> bool {
+        match (self.focused(), code) {
+            (_, KeyCode::Tab) => self.focus = (self.focus + 1) % PANELS.len(),
+            (_, KeyCode::BackTab) => self.focus = (self.focus + PANELS.len() - 1) % PANELS.len(),
+            (_, KeyCode::Char('g')) => self.chart = ChartMetric::next(self.chart),
+            (_, KeyCode::Char('a')) => {
+                self.units = match self.units {
+                    Units::Percent => Units::Absolute,
+                    Units::Absolute => Units::Percent,
+                }
+            }
+            (Panel::Disks, KeyCode::Down | KeyCode::Char('j')) => {
+                let (disks, hidden) = stats.fullest_disks(self.max_disks);
+                let rows = stats.readonly_mounts.len() + stats.stale_mounts.len() + disks.len() + usize::from(hidden > 0);
+                self.disk_scroll = (self.disk_scroll + 1).min(rows.saturating_sub(1))
+            }
+            (Panel::Disks, KeyCode::Up | KeyCode::Char('k')) => self.disk_scroll = self.disk_scroll.saturating_sub(1),
+            (Panel::Disks, KeyCode::Char('s')) => {
+                self.disk_sort = match self.disk_sort {
+                    DiskSort::Mount => DiskSort::Usage,
+                    DiskSort::Usage => DiskSort::Mount,
+                };
+                self.disk_scroll = 0;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn block(&self, panel: Panel) -> Block<'static> {
+        let block = Block::default().borders(Borders::ALL);
+        if self.focused() == panel {
+            block.border_type(BorderType::Thick).border_style(Style::default().fg(self.theme.accent))
+        } else {
+            block
+        }
+    }
+}
+
+// Braille draws the finest lines but needs a UTF-8 terminal whose font has the glyphs. The legacy
+// Windows console usually lacks them while Windows Terminal (which sets WT_SESSION) has them; elsewhere
+// only an explicitly non-UTF-8 locale rules them out.
+pub fn chart_marker(choice: ChartMarker) -> symbols::Marker {
+    match choice {
+        ChartMarker::Braille => symbols::Marker::Braille,
+        ChartMarker::Dot => symbols::Marker::Dot,
+        ChartMarker::Block => symbols::Marker::Block,
+        ChartMarker::Auto if cfg!(windows) => {
+            if std::env::var_os("WT_SESSION").is_some() {
+                symbols::Marker::Braille
+            } else {
+                symbols::Marker::Dot
+            }
+        }
+        ChartMarker::Auto => {
+            let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+                .iter()
+                .filter_map(|name| std::env::var(name).ok())
+                .find(|value| !value.is_empty());
+            match locale {
+                Some(locale) if !locale.to_uppercase().replace('-', "").contains("UTF8") => symbols::Marker::Dot,
+                _ => symbols::Marker::Braille,
+            }
+        }
+    }
+}
+
+// Takes a new sample in place of the previous one, which it still needs for the rates, and
+// records it in `history`. The kernel counters cost another command, so they're only read when asked for.
+pub fn refresh_stats(
+    stats: &mut SystemStats,
+    history: &mut MetricHistory,
+    sess: &impl RemoteExec,
+    kernel_metrics: bool,
+) -> Result<(), SshError> {
+    let mut fresh = gather_stats(sess)?;
+    if kernel_metrics {
+        fresh.kernel = read_kernel_metrics(sess)?;
+        fresh.update_kernel_rates(stats);
+    }
+    fresh.update_swap_rate(stats);
+    fresh.update_oom_kills(stats);
+    fresh.update_container_cpu(stats);
+    history.record(&fresh);
+    *stats = fresh;
+    Ok(())
+}
+
+// Spreads a history over the chart width; a single sample is drawn as a flat line
+fn history_points(history: &[f64], current: f64, width: f64) -> Vec<(f64, f64)> {
+    // Ensure we have at least two points
+    let history = if history.is_empty() { vec![current, current] } else { history.to_vec() };
+    history
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| {
+            let x = if history.len() > 1 { (i as f64 / (history.len() - 1) as f64) * width } else { 0.0 };
+            (x, v)
+        })
+        .collect()
+}
+
+// "-5m" ... "now" labels for the time a history of `samples` covers
+fn history_span_labels(samples: usize, interval: u64) -> Vec<Span<'static>> {
+    let seconds = samples.saturating_sub(1) as u64 * interval;
+    let span = if seconds >= 3600 {
+        format!("-{}h{:02}m", seconds / 3600, seconds % 3600 / 60)
+    } else if seconds >= 60 {
+        format!("-{}m{:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("-{}s", seconds)
+    };
+    vec![Span::raw(span), Span::raw("now")]
+}
+
+// Rows the dashboard needs to show the history charts
+pub const DASHBOARD_HEIGHT: u16 = 20;
+
+// CPU, load and connection history side by side, or the one chart picked with `g` across the whole width
+fn draw_history_charts(f: &mut Frame, area: Rect, stats: &SystemStats, history: &MetricHistory, view: &DashboardView) {
+    match view.chart {
+        Some(ChartMetric::Cpu) => draw_cpu_chart(f, area, stats, history, view),
+        Some(ChartMetric::Memory) => draw_memory_chart(f, area, stats, history, view),
+        Some(ChartMetric::Load) => draw_load_chart(f, area, stats, history, view),
+        Some(ChartMetric::Connections) => draw_connections_chart(f, area, stats, history, view),
+        None => {
+            let chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(40), Constraint::Percentage(30), Constraint::Percentage(30)])
+                .split(area);
+            draw_cpu_chart(f, chunks[0], stats, history, view);
+            draw_load_chart(f, chunks[1], stats, history, view);
+            draw_connections_chart(f, chunks[2], stats, history, view);
+        }
+    }
+}
+
+fn draw_cpu_chart(f: &mut Frame, area: Rect, stats: &SystemStats, history: &MetricHistory, view: &DashboardView) {
+    let theme = &view.theme;
+    let width = area.width as f64;
+    let cpu_points = history_points(&history.cpu, stats.cpu_usage, width);
+
+    let datasets = vec![
+        Dataset::default()
+            .name("CPU %")
+            .marker(view.marker)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(theme.accent))
+            .data(&cpu_points)
+    ];
+
+    let mut cpu_title = vec![Span::raw(if stats.available("cpu") {
+        format!("CPU Usage: {:.1}%", stats.cpu_usage)
+    } else {
+        "CPU Usage: unavailable".to_string()
+    })];
+    // Steal explains a slow VM whose own CPU looks idle
+    if history.steal_persistent() {
+        cpu_title.push(Span::styled(
+            format!(" STEAL {:.1}% ", stats.cpu_steal),
+            Style::default().fg(theme.warn).add_modifier(Modifier::BOLD),
+        ));
+    } else if stats.cpu_steal > 0.0 {
+        cpu_title.push(Span::raw(format!(" steal {:.1}%", stats.cpu_steal)));
+    }
+    let cpu_chart = Chart::new(datasets)
+        .block(view.block(Panel::Cpu).title(Line::from(cpu_title)))
+        .x_axis(Axis::default()
+            .style(Style::default().fg(theme.muted))
+            .bounds([0.0, width])
+            .labels(history_span_labels(history.cpu.len(), history.interval)))
+        .y_axis(Axis::default()
+            .style(Style::default().fg(theme.muted))
+            .bounds([0.0, 100.0])
+            .labels(vec![Span::raw("0%"), Span::raw("50%"), Span::raw("100%")]));
+    f.render_widget(cpu_chart, area);
+}
+
+fn draw_memory_chart(f: &mut Frame, area: Rect, stats: &SystemStats, history: &MetricHistory, view: &DashboardView) {
+    let theme = &view.theme;
+    let width = area.width as f64;
+    let available = stats.available("memory") && stats.memory_total > 0;
+    let current = if available { stats.memory_percent() } else { 0.0 };
+    let memory_points = history_points(&history.memory, current, width);
+    let title = if available {
+        format!("Memory Usage: {:.1}%", current)
+    } else {
+        "Memory Usage: unavailable".to_string()
+    };
+    let memory_chart = Chart::new(vec![Dataset::default()
+        .name("Memory %")
+        .marker(view.marker)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(theme.usage(current)))
+        .data(&memory_points)])
+    .block(view.block(Panel::Memory).title(title))
+    .x_axis(Axis::default()
+        .style(Style::default().fg(theme.muted))
+        .bounds([0.0, width])
+        .labels(history_span_labels(history.memory.len(), history.interval)))
+    .y_axis(Axis::default()
+        .style(Style::default().fg(theme.muted))
+        .bounds([0.0, 100.0])
+        .labels(vec![Span::raw("0%"), Span::raw("50%"), Span::raw("100%")]));
+    f.render_widget(memory_chart, area);
+}
+
+// Load history, scaled so the core count line sits below the top; load above it means
+// processes are queueing for a CPU
+fn draw_load_chart(f: &mut Frame, area: Rect, stats: &SystemStats, history: &MetricHistory, view: &DashboardView) {
+    let theme = &view.theme;
+    let load_width = area.width as f64;
+    let load_points = history_points(&history.load, stats.load_average.0, load_width);
+    let cores = stats.cpu_count as f64;
+    let core_line = [(0.0, cores), (load_width, cores)];
+    let peak = load_points.iter().map(|&(_, v)| v).fold(cores, f64::max);
+    let load_top = (peak * 1.2).max(1.0);
+    let mut load_datasets = vec![
+        Dataset::default()
+            .name("Load 1m")
+            .marker(view.marker)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(theme.usage(if cores > 0.0 { stats.load_average.0 / cores * 100.0 } else { 0.0 })))
+            .data(&load_points),
+    ];
+    if stats.cpu_count > 0 {
+        load_datasets.push(
+            Dataset::default()
+                .name(format!("{} cores", stats.cpu_count))
+                .marker(view.marker)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(theme.critical))
+                .data(&core_line),
+        );
+    }
+    let load_title = if !stats.available("load") {
+        "Load: unavailable".to_string()
+    } else if stats.cpu_count > 0 {
+        format!("Load (1m): {:.2} / {} cores", stats.load_average.0, stats.cpu_count)
+    } else {
+        format!("Load (1m): {:.2}", stats.load_average.0)
+    };
+    let load_chart = Chart::new(load_datasets)
+        .block(view.block(Panel::Load).title(load_title))
+        .x_axis(Axis::default()
+            .style(Style::default().fg(theme.muted))
+            .bounds([0.0, load_width])
+            .labels(history_span_labels(history.load.len(), history.interval)))
+        .y_axis(Axis::default()
+            .style(Style::default().fg(theme.muted))
+            .bounds([0.0, load_top])
+            .labels(vec![Span::raw("0"), Span::raw(format!("{:.1}", load_top / 2.0)), Span::raw(format!("{:.1}", load_top))]));
+    f.render_widget(load_chart, area);
+}
+
+// Established connections; a sudden climb often comes before the CPU or memory trouble
+fn draw_connections_chart(f: &mut Frame, area: Rect, stats: &SystemStats, history: &MetricHistory, view: &DashboardView) {
+    let theme = &view.theme;
+    let connections_width = area.width as f64;
+    let current = stats.tcp_established.unwrap_or(0) as f64;
+    let connection_points = history_points(&history.connections, current, connections_width);
+    let connections_top = (connection_points.iter().map(|&(_, v)| v).fold(0.0, f64::max) * 1.2).max(10.0).ceil();
+    let connections_title = match stats.tcp_established {
+        Some(count) => format!("Connections: {} established", count),
+        None => "Connections: unavailable".to_string(),
+    };
+    let connections_chart = Chart::new(vec![Dataset::default()
+        .name("TCP estab")
+        .marker(view.marker)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(theme.accent))
+        .data(&connection_points)])
+    .block(view.block(Panel::Connections).title(connections_title))
+    .x_axis(Axis::default()
+        .style(Style::default().fg(theme.muted))
+        .bounds([0.0, connections_width])
+        .labels(history_span_labels(history.connections.len(), history.interval)))
+    .y_axis(Axis::default()
+        .style(Style::default().fg(theme.muted))
+        .bounds([0.0, connections_top])
+        .labels(vec![Span::raw("0"), Span::raw(format!("{:.0}", connections_top / 2.0)), Span::raw(format!("{:.0}", connections_top))]));
+    f.render_widget(connections_chart, area);
+}
+
+// The compact layout's stand-in for the charts: current CPU usage, and the 1-minute load as a
+// share of the core count, so 100% means every core is busy
+fn draw_usage_gauges(f: &mut Frame, area: Rect, stats: &SystemStats, view: &DashboardView) {
+    let theme = &view.theme;
+    let gauge_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+
+    let cpu = Some(stats.cpu_usage).filter(|_| stats.available("cpu"));
+    let cpu_gauge = Gauge::default()
+        .block(view.block(Panel::Cpu).title("CPU Usage"))
+        .gauge_style(Style::default().fg(theme.usage(cpu.unwrap_or(0.0))))
+        .ratio(cpu.map_or(0.0, |percent| (percent / 100.0).clamp(0.0, 1.0)))
+        .label(cpu.map_or_else(|| "unavailable".to_string(), |percent| format!("{:.1}%", percent)));
+    f.render_widget(cpu_gauge, gauge_chunks[0]);
+
+    let load = Some(stats.load_average.0 / stats.cpu_count as f64 * 100.0)
+        .filter(|percent| stats.available("load") && percent.is_finite());
+    let load_gauge = Gauge::default()
+        .block(view.block(Panel::Load).title("Load (1m) of cores"))
+        .gauge_style(Style::default().fg(theme.usage(load.unwrap_or(0.0))))
+        .ratio(load.map_or(0.0, |percent| (percent / 100.0).clamp(0.0, 1.0)))
+        .label(load.map_or_else(
+            || "unavailable".to_string(),
+            |percent| format!("{:.0}% ({:.2} / {} cores)", percent, stats.load_average.0, stats.cpu_count),
+        ));
+    f.render_widget(load_gauge, gauge_chunks[1]);
+}
+
+// The single-host dashboard: system info, CPU history, memory bars and disk list
+pub fn draw_dashboard(
+    f: &mut Frame,
+    stats: &SystemStats,
+    history: &MetricHistory,
+    alerts: &[Alert],
+    notice: Option<&str>,
+    help: &str,
+    view: &DashboardView,
+) {
+    let area = f.size();
+    // Gauges instead of charts when asked for, or when the charts wouldn't fit
+    let compact = view.compact || area.height < DASHBOARD_HEIGHT;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),                            // System info
+            Constraint::Length(if compact { 3 } else { 10 }), // CPU and load history, or gauges
+            Constraint::Length(3),  // Memory bars
+            Constraint::Length(4),  // Further reduced disk usage section from 6 to 4
+            Constraint::Length(if stats.kernel.is_some() { 3 } else { 0 }), // Kernel counters
+        ].as_ref())
+        .split(area);
+
+    let theme = &view.theme;
+
+    // System info (uptime + load)
+    let uptime = if stats.available("load") { stats.uptime.clone() } else { "uptime and load unavailable".to_string() };
+    let mut uptime_line = vec![
+        Span::raw(uptime),
+        Span::raw(" "),
+        Span::styled(help.to_string(), Style::default().fg(theme.muted)),
+    ];
+    if let Some(notice) = notice {
+        uptime_line.push(Span::raw(" "));
+        uptime_line.push(Span::styled(notice.to_string(), Style::default().fg(theme.critical)));
+    }
+    let uptime_text = Text::from(vec![Line::from(uptime_line)]);
+    let mut system_title = vec![Span::raw(match &stats.os {
+        Some(os) => format!("System: {}", os),
+        None => "System".to_string(),
+    })];
+    // Memory, cores and CPU usage are the container's, so the figures aren't taken for the machine's
+    if let Some(container) = &stats.container {
+        system_title.push(Span::styled(format!(" [container view: {}]", container), Style::default().fg(theme.accent)));
+    }
+    if !alerts.is_empty() {
+        let summary = alerts
+            .iter()
+            .map(|a| format!("{} {:.0}%", a.metric, a.value))
+            .collect::<Vec<_>>()
+            .join(", ");
+        system_title.push(Span::styled(
+            format!(" ALERT: {} ", summary),
+            Style::default().fg(theme.critical).add_modifier(Modifier::BOLD),
+        ));
+    }
+    let mut system_block = Block::default().borders(Borders::ALL).title(Line::from(system_title));
+    if view.color_scale {
+        system_block = system_block
+            .title(block::Title::from(theme.scale_key()).position(block::Position::Bottom).alignment(Alignment::Right));
+    }
+    let uptime_widget = Paragraph::new(uptime_text).block(system_block);
+    f.render_widget(uptime_widget, chunks[0]);
+
+    if compact {
+        draw_usage_gauges(f, chunks[1], stats, view);
+    } else {
+        draw_history_charts(f, chunks[1], stats, history, view);
+    }
+
+    // Memory usage
+    let mem_percent = (stats.memory_used as f64 / stats.memory_total as f64 * 100.0) as u64;
+    let swap_percent = (stats.swap_used as f64 / stats.swap_total as f64 * 100.0) as u64;
+    let memory_bars = [("Memory", mem_percent, stats.memory_used), ("Swap", swap_percent, stats.swap_used)]
+        .into_iter()
+        .map(|(label, percent, used)| {
+            Bar::default()
+                .label(label.into())
+                .value(percent)
+                .text_value(match view.units {
+                    Units::Percent => format!("{}%", percent),
+                    Units::Absolute => format_size(used, BINARY),
+                })
+                .style(Style::default().fg(theme.usage(percent as f64)))
+        })
+        .collect::<Vec<_>>();
+
+    let mut memory_title = vec![Span::raw(if stats.available("memory") { "Memory" } else { "Memory: unavailable" })];
+    // Any swapping at all is worth a look, even when little swap is in use
+    if let Some((swapped_in, swapped_out)) = stats.swap_rate {
+        let color = if swapped_in > 0.0 || swapped_out > 0.0 { theme.warn } else { theme.muted };
+        memory_title.push(Span::styled(
+            format!(" swap in {:.0}/s out {:.0}/s pages ", swapped_in, swapped_out),
+            Style::default().fg(color),
+        ));
+    }
+    // A kill explains a process that vanished; keep it on screen long enough to be noticed
+    const OOM_WARNING: Duration = Duration::from_secs(15 * 60);
+    if let Some(elapsed) = stats.last_oom_kill.map(|at| at.elapsed()).filter(|elapsed| *elapsed < OOM_WARNING) {
+        memory_title.push(Span::styled(
+            format!(" OOM KILL {}m ago ", elapsed.as_secs() / 60),
+            Style::default().fg(theme.critical).add_modifier(Modifier::BOLD),
+        ));
+    }
+    let barchart = BarChart::default()
+        .block(view.block(Panel::Memory).title(Line::from(memory_title)))
+        .data(BarGroup::default().bars(&memory_bars))
+        .bar_width(10)
+        .group_gap(3)
+        .max(100);
+    f.render_widget(barchart, chunks[2]);
+
+    // Disk usage
+    let (mut disks, hidden) = stats.fullest_disks(view.max_disks);
+    if view.disk_sort == DiskSort::Usage {
+        disks.sort_by(|(_, total_a, used_a), (_, total_b, used_b)| {
+            (*used_b as f64 / *total_b as f64).total_cmp(&(*used_a as f64 / *total_a as f64))
+        });
+    }
+    // Read-only and stale mounts go first, where a long list can't push them out of sight
+    let problem = Style::default().fg(theme.critical).add_modifier(Modifier::REVERSED);
+    let readonly_items = stats.readonly_mounts.iter().map(|ro| {
+        ListItem::new(format!("! {}: READ-ONLY ({} on {})", ro.mount, ro.fstype, ro.device)).style(problem)
+    });
+    let stale_items = stats
+        .stale_mounts
+        .iter()
+        .map(|mount| ListItem::new(format!("! {}: unavailable (stale or unreachable)", mount)).style(problem));
+    let disk_items: Vec<ListItem> = readonly_items
+        .chain(stale_items)
+        .chain(disks.into_iter().map(|(mount, total, used)| {
+            let percentage = *used as f64 / *total as f64 * 100.0;
+            let usage = match view.units {
+                Units::Percent => format!("{}% of {}", percentage as u8, format_size(*total, BINARY)),
+                Units::Absolute => format!(
+                    "{} / {} ({} free)",
+                    format_size(*used, BINARY),
+                    format_size(*total, BINARY),
+                    format_size(total.saturating_sub(*used), BINARY)
+                ),
+            };
+            let text = format!("{} {}: {}", history.disk_trend(mount, *total), mount, usage);
+            ListItem::new(text).style(Style::default().fg(theme.usage(percentage)))
+        }))
+        .chain((hidden > 0).then(|| {
+            ListItem::new(format!("... and {} more", hidden)).style(Style::default().fg(theme.muted))
+        }))
+        .skip(view.disk_scroll)
+        .collect();
+    let disk_title = if !stats.available("disk") {
+        "Disk Usage: unavailable".to_string()
+    } else if view.disk_scroll > 0 {
+        format!("Disk Usage ({} more above)", view.disk_scroll)
+    } else if view.disk_sort == DiskSort::Usage {
+        "Disk Usage (fullest first)".to_string()
+    } else {
+        "Disk Usage".to_string()
+    };
+    let mut disk_block = view.block(Panel::Disks).title(disk_title);
+    if let Some(line) = disk_totals_line(stats) {
+        disk_block = disk_block
+            .title(block::Title::from(format!("Total: {}", line)).position(block::Position::Bottom).alignment(Alignment::Right));
+    }
+    let disk_list = List::new(disk_items).block(disk_block);
+    f.render_widget(disk_list, chunks[3]);
+
+    if let Some(kernel) = &stats.kernel {
+        let advanced = Paragraph::new(kernel_line(kernel))
+            .block(Block::default().borders(Borders::ALL).title("Kernel"));
+        f.render_widget(advanced, chunks[4]);
+    }
+}
+
+// Rates need two samples, so the first one only shows the run queue
+fn kernel_line(kernel: &KernelMetrics) -> String {
+    let rates = kernel.rates.map_or_else(String::new, |(switches, interrupts)| {
+        format!("context switches {:.0}/s, interrupts {:.0}/s, ", switches, interrupts)
+    });
+    format!("{}run queue {} of {} tasks, {} blocked on I/O", rates, kernel.runnable, kernel.tasks, kernel.blocked)
+}
+
+/// A recap of a `monitor` session, printed after the terminal is restored.
+pub struct SessionSummary {
+    pub host: String,
+    pub started: Instant,
+    pub samples: usize,
+    pub peak_cpu: Option<f64>,
+    pub peak_memory: Option<f64>,
+    pub breaches: Vec<(String, usize, f64)>, // (metric, times it crossed its threshold, highest value)
+}
+
+impl SessionSummary {
+    pub fn new(host: &str) -> Self {
+        SessionSummary {
+            host: host.to_string(),
+            started: Instant::now(),
+            samples: 0,
+            peak_cpu: None,
+            peak_memory: None,
+            breaches: Vec::new(),
+        }
+    }
+
+    // `breached` is what was over its threshold in the previous sample, so a sustained breach
+    // counts once, as the webhook reports it
+    pub fn record(&mut self, stats: &SystemStats, alerts: &[Alert], breached: &[String]) {
+        let peak = |peak: Option<f64>, value: f64| Some(peak.map_or(value, |peak| peak.max(value)));
+        self.samples += 1;
+        if stats.available("cpu") {
+            self.peak_cpu = peak(self.peak_cpu, stats.cpu_usage);
+        }
+        if stats.available("memory") && stats.memory_percent().is_finite() {
+            self.peak_memory = peak(self.peak_memory, stats.memory_percent());
+        }
+        for alert in alerts {
+            let index = match self.breaches.iter().position(|(metric, _, _)| *metric == alert.metric) {
+                Some(index) => index,
+                None => {
+                    self.breaches.push((alert.metric.clone(), 0, alert.value));
+                    self.breaches.len() - 1
+                }
+            };
+            let (_, times, highest) = &mut self.breaches[index];
+            if !breached.contains(&alert.metric) {
+                *times += 1;
+            }
+            *highest = highest.max(alert.value);
+        }
+    }
+}
+
+impl std::fmt::Display for SessionSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let duration = format_duration(self.started.elapsed().as_secs());
+        let percent = |peak: Option<f64>| peak.map_or_else(|| "n/a".to_string(), |peak| format!("{:.1}%", peak));
+        writeln!(f, "Monitored {} for {}: {} samples", self.host, duration, self.samples)?;
+        writeln!(f, "Peak CPU: {}", percent(self.peak_cpu))?;
+        writeln!(f, "Peak memory: {}", percent(self.peak_memory))?;
+        if self.breaches.is_empty() {
+            return write!(f, "Threshold breaches: none");
+        }
+        let breaches: Vec<String> = self
+            .breaches
+            .iter()
+            .map(|(metric, times, highest)| {
+                let times = if *times == 1 { "once".to_string() } else { format!("{} times", times) };
+                format!("{} {} (peak {:.1}%)", metric, times, highest)
+            })
+            .collect();
+        write!(f, "Threshold breaches: {}", breaches.join(", "))
+    }
+}
+
+// The alerts that fire: metrics newly over their threshold (not in `breached` from the previous
+// sample), unless the same metric fired within `cooldown`, which keeps one flapping around its
+// threshold from paging on every crossing. `fired` keeps when each metric last fired.
+fn firing(alerts: &[Alert], breached: &[String], fired: &mut Vec<(String, Instant)>, cooldown: Option<Duration>) -> Vec<Alert> {
+    let mut firing = Vec::new();
+    for alert in alerts.iter().filter(|alert| !breached.contains(&alert.metric)) {
+        match fired.iter_mut().find(|(metric, _)| *metric == alert.metric) {
+            Some((_, at)) if cooldown.is_some_and(|cooldown| at.elapsed() < cooldown) => continue,
+            Some((_, at)) => *at = Instant::now(),
+            None => fired.push((alert.metric.clone(), Instant::now())),
+        }
+        firing.push(alert.clone());
+    }
+    firing
+}
+
+// The per-sample work shared by the dashboard and `--no-tui`: refresh the stats, check the
+// thresholds, fire webhooks and store the sample
+struct Sampler {
+    stats: SystemStats,
+    history: MetricHistory,
+    client: reqwest::Client,
+    // Metrics currently over their threshold; an alert only fires when a metric newly enters
+    // this set, so a sustained breach is reported once rather than on every sample
+    breached: Vec<String>,
+    fired: Vec<(String, Instant)>, // when each metric last fired, for --alert-cooldown
+    alerts: Vec<Alert>,
+    webhook_error: Arc<Mutex<Option<String>>>,
+    db_error: Option<String>,
+    alert_log_error: Option<String>,
+    slowest_command: Option<(String, Duration)>, // with --verbose
+    reconnects: usize,
+    summary: SessionSummary,
+}
+
+impl Sampler {
+    fn new(options: &MonitorOptions) -> Self {
+        Sampler {
+            stats: SystemStats::default(),
+            history: MetricHistory::new(options.history, options.interval),
+            client: reqwest::Client::new(),
+            breached: Vec::new(),
+            fired: Vec::new(),
+            alerts: Vec::new(),
+            webhook_error: Arc::new(Mutex::new(None)),
+            db_error: None,
+            alert_log_error: None,
+            slowest_command: None,
+            reconnects: 0,
+            summary: SessionSummary::new(&options.host),
+        }
+    }
+
+    fn sample(&mut self, sess: &Remote, options: &MonitorOptions) -> Result<()> {
+        let sess = Retrying { inner: sess, retries: options.retries };
+        take_slowest_command();
+        refresh_stats(&mut self.stats, &mut self.history, &sess, options.kernel_metrics)?;
+        self.slowest_command = take_slowest_command();
+
+        self.alerts = check_thresholds(&self.stats, &options.thresholds);
+        for alert in firing(&self.alerts, &self.breached, &mut self.fired, options.alert_cooldown) {
+            if let Some(url) = &options.alert_webhook {
+                let webhook_error = self.webhook_error.clone();
+                let request =
+                    send_webhook_alert(self.client.clone(), url.clone(), options.host.clone(), alert.clone());
+                tokio::spawn(async move {
+                    let result = request.await.err().map(|e| format!("Webhook failed: {:#}", e));
+                    *webhook_error.lock().unwrap_or_else(|e| e.into_inner()) = result;
+                });
+            }
+            if let Some(log) = &options.alert_log {
+                self.alert_log_error =
+                    log.record(&options.host, &alert).err().map(|e| format!("Alert log write failed: {:#}", e));
+            }
+        }
+        self.summary.record(&self.stats, &self.alerts, &self.breached);
+        self.breached = self.alerts.iter().map(|a| a.metric.clone()).collect();
+
+        if let Some(db) = &options.db {
+            let sample = StoredSample::from_stats(&self.stats, chrono::Utc::now().timestamp());
+            self.db_error = db.record(&options.host, &sample).err().map(|e| format!("Database write failed: {:#}", e));
+        }
+        Ok(())
+    }
+
+    // Samples like `sample`, but a session the server closed (an idle or session time limit, say)
+    // is opened again and the sample retried once before the error ends the monitor
+    fn sample_or_reconnect(&mut self, sess: &mut Remote, options: &MonitorOptions) -> Result<()> {
+        match self.sample(sess, options) {
+            Err(e) if matches!(e.downcast_ref::<SshError>(), Some(SshError::SessionClosed { .. })) => {
+                *sess = reconnect(sess, &options.target).with_context(|| format!("{:#}, and reconnecting failed", e))?;
+                self.reconnects += 1;
+                self.sample(sess, options)
+            }
+            result => result,
+        }
+    }
+
+    // The latest problem storing or reporting a sample, if any
+    fn notice(&self) -> Option<String> {
+        self.db_error
+            .clone()
+            .or_else(|| self.alert_log_error.clone())
+            .or_else(|| self.webhook_error.lock().unwrap_or_else(|e| e.into_inner()).clone())
+            .or_else(|| match self.reconnects {
+                0 => None,
+                1 => Some("Reconnected after the server closed the session".to_string()),
+                n => Some(format!("Reconnected {} times after the server closed the session", n)),
+            })
+            .or_else(|| {
+                let (command, elapsed) = self.slowest_command.as_ref()?;
+                Some(redact(&format!("slowest command: {} ({:.2}s)", command, elapsed.as_secs_f64())))
+            })
+    }
+}
+
+// The sampling intervals `+` and `-` step through while monitoring, in seconds. An --interval
+// between two of them steps to the neighbouring one; one outside the range can only come back in.
+const INTERVAL_STEPS: [u64; 9] = [1, 2, 5, 10, 15, 30, 60, 120, 300];
+
+fn step_interval(interval: u64, faster: bool) -> u64 {
+    let (first, last) = (INTERVAL_STEPS[0], INTERVAL_STEPS[INTERVAL_STEPS.len() - 1]);
+    if faster {
+        INTERVAL_STEPS.iter().rev().copied().find(|&step| step < interval).unwrap_or(interval.min(first))
+    } else {
+        INTERVAL_STEPS.iter().copied().find(|&step| step > interval).unwrap_or(interval.max(last))
+    }
+}
+
+pub async fn monitor_system(sess: &mut Remote, options: &MonitorOptions) -> Result<SessionSummary> {
+    use std::io::IsTerminal;
+    // Redirected output gets what --no-tui prints rather than a screen of escape codes, unless
+    // there's a dashboard to record, where setup_terminal says why it can't start
+    if !std::io::stdout().is_terminal() && options.record.is_none() {
+        return monitor_plain(sess, options).await;
+    }
+    let mut view = DashboardView::new(options.marker, options.color_scale, options.units, options.compact);
+    view.max_disks = options.max_disks;
+    // Loaded before raw mode, so a theme that can't be read doesn't leave the terminal in it
+    let mut theme_watcher = None;
+    if let Some(path) = &options.theme_file {
+        let (watcher, theme) = ThemeWatcher::new(path.clone())?;
+        (theme_watcher, view.theme) = (Some(watcher), theme);
+    }
+    let (mut terminal, guard) = setup_terminal(options.record.as_deref())?;
+
+    // None until the first sample, which is taken right away rather than an interval in, so the
+    // dashboard never starts out showing zeros
+    let mut last_update: Option<Instant> = None;
+    let mut sampler = Sampler::new(options);
+    let mut clipboard_notice: Option<String> = None;
+    let mut theme_error: Option<String> = None;
+    let mut interval = options.interval; // changed with + and -
+
+    loop {
+        if last_update.is_none_or(|at| at.elapsed() >= Duration::from_secs(interval)) {
+            sampler.sample_or_reconnect(sess, options)?;
+            last_update = Some(Instant::now());
+        }
+
+        // A broken edit keeps the last good palette on screen until the file is fixed
+        match theme_watcher.as_mut().and_then(ThemeWatcher::poll) {
+            Some(Ok(theme)) => (view.theme, theme_error) = (theme, None),
+            Some(Err(e)) => theme_error = Some(format!("{:#}", e)),
+            None => {}
+        }
+
+        let notice = clipboard_notice.clone().or(theme_error.clone()).or_else(|| sampler.notice());
+
+        terminal.draw(|f| {
+            let help = format!(
+                "every {}s (q: quit, c: copy, +/-: interval, a: units, g: chart, Tab: focus, j/k: scroll, s: sort)",
+                interval
+            );
+            draw_dashboard(f, &sampler.stats, &sampler.history, &sampler.alerts, notice.as_deref(), &help, &view);
+        })?;
+
+        if event::poll(Duration::from_millis(200))? {
+            match event::read()? {
+                // Pick up the new size right away so the next draw lays out against it
+                // instead of the buffers from before the resize
+                Event::Resize(_, _) => terminal.autoresize()?,
+                Event::Key(key) if view.handle_key(key.code, &sampler.stats) => {}
+                Event::Key(key) => match key.code {
+                    KeyCode::Char('q') => break,
+                    KeyCode::Char('c') => {
+                        let summary = get_fleet_table(
+                            &[(options.host.clone(), Ok(sampler.stats.clone()))],
+                            OutputFormat::Table,
+                            options.min_uptime,
+                        );
+                        clipboard_notice = Some(match copy_to_clipboard(&summary) {
+                            Ok(()) => "Copied to clipboard".to_string(),
+                            Err(e) => format!("Clipboard unavailable: {}", e),
+                        });
+                    }
+                    // A faster interval takes effect right away if the last sample is already older.
+                    // The charts' time axis follows the new interval, so it's approximate until
+                    // the older samples have scrolled off.
+                    KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Char('-') => {
+                        interval = step_interval(interval, key.code != KeyCode::Char('-'));
+                        sampler.history.interval = interval;
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+    }
+
+    guard.restore()?;
+    Ok(sampler.summary)
+}
+
+// One sample as plain text for `monitor --no-tui`
+pub fn plain_stats(host: &str, stats: &SystemStats, alerts: &[Alert], max_disks: Option<usize>) -> String {
+    let mut text = format!("{} at {}\n", host, format_time(chrono::Utc::now(), "%H:%M:%S"));
+    if let Some(container) = &stats.container {
+        text.push_str(&format!("{:<8}container ({})\n", "View", container));
+    }
+    let mut line = |label: &str, metric: &str, value: String| {
+        let value = if stats.available(metric) { value } else { "unavailable".to_string() };
+        text.push_str(&format!("{:<8}{}\n", label, value));
+    };
+    line("CPU", "cpu", format!("{:.1}% (steal {:.1}%)", stats.cpu_usage, stats.cpu_steal));
+    let (one, five, fifteen) = stats.load_average;
+    line("Load", "load", format!("{:.2} {:.2} {:.2} ({} cores)", one, five, fifteen, stats.cpu_count));
+    let used_of = |used: u64, total: u64| {
+        format!("{} / {} ({:.0}%)", format_size(used, BINARY), format_size(total, BINARY), used as f64 / total as f64 * 100.0)
+    };
+    line("Memory", "memory", used_of(stats.memory_used, stats.memory_total));
+    if stats.swap_total > 0 {
+        line("Swap", "memory", used_of(stats.swap_used, stats.swap_total));
+    }
+    let (disks, hidden) = stats.fullest_disks(max_disks);
+    for (mount, total, used) in disks {
+        line("Disk", "disk", format!("{} {}", mount, used_of(*used, *total)));
+    }
+    if hidden > 0 {
+        line("Disk", "disk", format!("... and {} more", hidden));
+    }
+    for mount in &stats.stale_mounts {
+        line("Disk", "disk", format!("{} unavailable (stale or unreachable)", mount));
+    }
+    if let Some(count) = stats.tcp_established {
+        line("Conns", "connections", format!("{} established", count));
+    }
+    if let Some(kernel) = &stats.kernel {
+        line("Kernel", "kernel", kernel_line(kernel));
+    }
+    if let Some(seconds) = stats.uptime_seconds {
+        line("Uptime", "load", format_uptime(seconds));
+    }
+    for ro in &stats.readonly_mounts {
+        text.push_str(&format!("ALERT   {} is read-only ({} on {})\n", ro.mount, ro.fstype, ro.device));
+    }
+    for alert in alerts {
+        text.push_str(&format!("ALERT   {} {:.0}% (threshold {:.0}%)\n", alert.metric, alert.value, alert.threshold));
+    }
+    text
+}
+
+// The monitor without a TUI, for dumb terminals and CI logs: prints each sample as a block of
+// text, over the previous one on a terminal and appended below it otherwise. Ctrl-C ends it.
+pub async fn monitor_plain(sess: &mut Remote, options: &MonitorOptions) -> Result<SessionSummary> {
+    use std::io::IsTerminal;
+    let clear = std::io::stdout().is_terminal() && std::env::var("TERM").map_or(true, |term| term != "dumb");
+    let mut sampler = Sampler::new(options);
+    loop {
+        sampler.sample_or_reconnect(sess, options)?;
+        let mut block = plain_stats(&redact(&options.host), &sampler.stats, &sampler.alerts, options.max_disks);
+        if let Some(notice) = sampler.notice() {
+            block.push_str(&format!("{}\n", notice));
+        }
+        if clear {
+            print!("\x1b[2J\x1b[H{}", block);
+        } else {
+            println!("{}", block);
+        }
+        std::io::Write::flush(&mut std::io::stdout())?;
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = tokio::time::sleep(Duration::from_secs(options.interval)) => {}
+        }
+    }
+    Ok(sampler.summary)
+}
+
+/// Where a `replay` is in its samples and how fast it moves through them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayState {
+    pub position: usize, // index of the sample on screen
+    pub speed: f64,      // recorded seconds per second of replay
+    pub paused: bool,
+}
+
+impl ReplayState {
+    // Returns false if the key means nothing to the replay, so the caller can handle it
+    pub fn handle_key(&mut self, code: KeyCode, samples: usize) -> bool {
+        let last = samples.saturating_sub(1);
+        let page = (samples / 10).max(1);
+        match code {
+            KeyCode::Char(' ') => self.paused = !self.paused,
+            KeyCode::Right | KeyCode::Char('l') => self.position = (self.position + 1).min(last),
+            KeyCode::Left | KeyCode::Char('h') => self.position = self.position.saturating_sub(1),
+            KeyCode::PageDown => self.position = (self.position + page).min(last),
+            KeyCode::PageUp => self.position = self.position.saturating_sub(page),
+            KeyCode::Home => self.position = 0,
+            KeyCode::End => self.position = last,
+            KeyCode::Char('+') | KeyCode::Char('=') => self.speed = (self.speed * 2.0).min(10_000.0),
+            KeyCode::Char('-') => self.speed = (self.speed / 2.0).max(0.25),
+            _ => return false,
+        }
+        true
+    }
+}
+
+// The stats and chart history as the monitor had them at `samples[position]`: the charts hold
+// up to `limit` samples leading up to it
+pub fn replay_frame(samples: &[StoredSample], position: usize, limit: usize) -> (SystemStats, MetricHistory) {
+    let window = &samples[(position + 1).saturating_sub(limit)..=position];
+    // The database keeps no interval, so the axis labels use the average gap in the window
+    let span = window.last().map_or(0, |s| s.timestamp) - window.first().map_or(0, |s| s.timestamp);
+    let interval = if window.len() > 1 { (span / (window.len() as i64 - 1)).max(1) as u64 } else { 1 };
+    let mut history = MetricHistory::new(limit, interval);
+    for sample in window {
+        history.record(&sample.to_stats());
+    }
+    (samples[position].to_stats(), history)
+}
+
+// The recorded metrics through the monitor's charts, with the fullest disk and a progress bar
+// for seeking below them
+fn draw_replay(f: &mut Frame, host: &str, samples: &[StoredSample], state: &ReplayState, history: &MetricHistory, stats: &SystemStats, view: &DashboardView) {
+    let theme = &view.theme;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(8), Constraint::Length(3), Constraint::Length(3)])
+        .split(f.size());
+
+    let sample = &samples[state.position];
+    let status = format!(
+        "{}  sample {} of {}  {}x{} ",
+        format_timestamp(sample.timestamp),
+        state.position + 1,
+        samples.len(),
+        state.speed,
+        if state.paused { " (paused)" } else { "" }
+    );
+    let help = "(q: quit, space: pause, h/l: step, PgUp/PgDn: seek, Home/End, +/-: speed, g: chart)";
+    let header = Paragraph::new(Line::from(vec![Span::raw(status), Span::styled(help, Style::default().fg(theme.muted))]))
+        .block(Block::default().borders(Borders::ALL).title(format!("Replay: {}", host)));
+    f.render_widget(header, chunks[0]);
+
+    // Connections aren't stored, so their chart only shows up when picked with `g`
+    match view.chart {
+        Some(ChartMetric::Cpu) => draw_cpu_chart(f, chunks[1], stats, history, view),
+        Some(ChartMetric::Memory) => draw_memory_chart(f, chunks[1], stats, history, view),
+        Some(ChartMetric::Load) => draw_load_chart(f, chunks[1], stats, history, view),
+        Some(ChartMetric::Connections) => draw_connections_chart(f, chunks[1], stats, history, view),
+        None => {
+            let charts = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(40), Constraint::Percentage(30), Constraint::Percentage(30)])
+                .split(chunks[1]);
+            draw_cpu_chart(f, charts[0], stats, history, view);
+            draw_memory_chart(f, charts[1], stats, history, view);
+            draw_load_chart(f, charts[2], stats, history, view);
+        }
+    }
+
+    let disk = Gauge::default()
+        .block(view.block(Panel::Disks).title("Fullest disk"))
+        .gauge_style(Style::default().fg(theme.usage(sample.disk.unwrap_or(0.0))))
+        .ratio(sample.disk.map_or(0.0, |percent| (percent / 100.0).clamp(0.0, 1.0)))
+        .label(sample.disk.map_or_else(|| "unavailable".to_string(), |percent| format!("{:.1}%", percent)));
+    f.render_widget(disk, chunks[2]);
+
+    let (first, last) = (samples[0].timestamp, samples[samples.len() - 1].timestamp);
+    let progress = Gauge::default()
+        .block(Block::default().borders(Borders::ALL))
+        .gauge_style(Style::default().fg(theme.accent))
+        .ratio(if samples.len() > 1 { state.position as f64 / (samples.len() - 1) as f64 } else { 1.0 })
+        .label(format!(
+            "{} of {}",
+            format_duration((sample.timestamp - first).max(0) as u64),
+            format_duration((last - first).max(0) as u64)
+        ));
+    f.render_widget(progress, chunks[3]);
+}
+
+// Plays stored samples back through the dashboard, `speed` recorded seconds per second. A gap
+// between recorded sessions is skipped over in at most a couple of seconds.
+pub fn replay_samples(host: &str, samples: &[StoredSample], speed: f64, history: usize, view: DashboardView) -> Result<()> {
+    if samples.is_empty() {
+        anyhow::bail!("No samples stored for {}", host);
+    }
+    const MAX_WAIT: Duration = Duration::from_secs(2);
+    let (mut terminal, guard) = setup_terminal(None)?;
+    let mut view = view;
+    let mut state = ReplayState { position: 0, speed, paused: false };
+    let mut shown_at = Instant::now();
+
+    loop {
+        let (stats, chart_history) = replay_frame(samples, state.position, history);
+        terminal.draw(|f| draw_replay(f, host, samples, &state, &chart_history, &stats, &view))?;
+
+        if !state.paused && state.position + 1 < samples.len() {
+            let gap = (samples[state.position + 1].timestamp - samples[state.position].timestamp).max(0) as f64;
+            if shown_at.elapsed() >= Duration::from_secs_f64(gap / state.speed).min(MAX_WAIT) {
+                state.position += 1;
+                shown_at = Instant::now();
+            }
+        }
+
+        if event::poll(Duration::from_millis(50))? {
+            match event::read()? {
+                Event::Resize(_, _) => terminal.autoresize()?,
+                Event::Key(key) if state.handle_key(key.code, samples.len()) => shown_at = Instant::now(),
+                Event::Key(key) if view.handle_key(key.code, &stats) => {}
+                Event::Key(key) if key.code == KeyCode::Char('q') => break,
+                _ => {}
+            }
+        }
+    }
+
+    guard.restore()?;
+    Ok(())
+}
+
+// Which lines of `current` differ from the line at the same position in `previous`, for
+// `watch --diff`. Nothing counts as changed before there is a previous run.
+pub fn changed_lines(previous: Option<&str>, current: &str) -> Vec<bool> {
+    let Some(previous) = previous else {
+        return vec![false; current.lines().count()];
+    };
+    let previous: Vec<&str> = previous.lines().collect();
+    current.lines().enumerate().map(|(i, line)| previous.get(i) != Some(&line)).collect()
+}
+
+// Runs `command` every `interval` seconds and shows its latest output full-screen, like watch(1)
+pub async fn watch_command(remote: &Remote, host: &str, command: &str, interval: u64, diff: bool) -> Result<()> {
+    let (mut terminal, guard) = setup_terminal(None)?;
+    let mut last_run: Option<Instant> = None;
+    let mut previous: Option<String> = None;
+    let mut lines: Vec<Line> = Vec::new();
+    let mut status = Line::default();
+
+    loop {
+        if last_run.is_none_or(|at| at.elapsed() >= Duration::from_secs(interval)) {
+            let header = Span::raw(format!(
+                "Every {}s on {}: {}  ({})",
+                interval,
+                redact(host),
+                command,
+                format_time(chrono::Utc::now(), "%H:%M:%S")
+            ));
+            let red = Style::default().fg(Color::Red);
+            match remote.run_command_output(command) {
+                Ok(output) => {
+                    let mut text = output.stdout;
+                    text.push_str(&output.stderr);
+                    let text = redact(&text);
+                    let changed = if diff { changed_lines(previous.as_deref(), &text) } else { Vec::new() };
+                    lines = text
+                        .lines()
+                        .enumerate()
+                        .map(|(i, line)| {
+                            if changed.get(i) == Some(&true) {
+                                Line::styled(line.to_string(), Style::default().add_modifier(Modifier::REVERSED))
+                            } else {
+                                Line::raw(line.to_string())
+                            }
+                        })
+                        .collect();
+                    let mut spans = vec![header];
+                    if output.exit_status != 0 {
+                        spans.push(Span::styled(format!("  exit status {}", output.exit_status), red));
+                    }
+                    status = Line::from(spans);
+                    previous = Some(text);
+                }
+                // Keep showing the last output; the next run may well succeed
+                Err(e) => status = Line::from(vec![header, Span::styled(format!("  {:#}", anyhow::Error::from(e)), red)]),
+            }
+            last_run = Some(Instant::now());
+        }
+
+        terminal.draw(|f| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(2), Constraint::Min(0)])
+                .split(f.size());
+            f.render_widget(Paragraph::new(status.clone()), chunks[0]);
+            f.render_widget(Paragraph::new(lines.clone()), chunks[1]);
+        })?;
+
+        if event::poll(Duration::from_millis(200))? {
+            match event::read()? {
+                Event::Resize(_, _) => terminal.autoresize()?,
+                Event::Key(key) if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) => break,
+                _ => {}
+            }
+        }
+    }
+
+    guard.restore()
+}
+
+// Live state of one host in the fleet grid, written by that host's sampling thread
+#[derive(Default)]
+pub struct FleetHost {
+    pub name: String,
+    pub stats: SystemStats,
+    pub history: MetricHistory,
+    pub sampled: bool,
+    pub error: Option<String>,
+}
+
+pub fn spawn_fleet_sampler(remote: Remote, host: Arc<Mutex<FleetHost>>, mut history: MetricHistory, stop: Arc<AtomicBool>) {
+    std::thread::spawn(move || {
+        let mut stats = SystemStats::default();
+        while !stop.load(Ordering::Relaxed) {
+            let result = refresh_stats(&mut stats, &mut history, &remote, false);
+            {
+                let mut host = host.lock().unwrap_or_else(|e| e.into_inner());
+                match result {
+                    Ok(()) => {
+                        host.stats = stats.clone();
+                        host.history = history.clone();
+                        host.sampled = true;
+                        host.error = None;
+                    }
+                    Err(e) => host.error = Some(format!("{:#}", anyhow::Error::from(e))),
+                }
+            }
+
+            // Sleep in short steps so quitting doesn't wait for a whole interval
+            let next_sample = Instant::now() + Duration::from_secs(history.interval);
+            while Instant::now() < next_sample && !stop.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+        }
+    });
+}
+
+pub const FLEET_CELL_WIDTH: u16 = 30;
+pub const FLEET_CELL_HEIGHT: u16 = 5;
+
+// One gauge line of a fleet cell, e.g. "CPU  ██████░░░░  62%"; None shows the metric as unavailable
+pub fn mini_gauge(label: &str, percent: Option<f64>, theme: &Theme) -> Line<'static> {
+    let Some(percent) = percent else {
+        return Line::from(vec![
+            Span::raw(format!("{:<5}", label)),
+            Span::styled("░".repeat(10), Style::default().fg(theme.muted)),
+            Span::raw("  n/a"),
+        ]);
+    };
+    let filled = ((percent / 10.0).round() as usize).min(10);
+    Line::from(vec![
+        Span::raw(format!("{:<5}", label)),
+        Span::styled(
+            format!("{}{}", "█".repeat(filled), "░".repeat(10 - filled)),
+            Style::default().fg(theme.usage(percent)),
+        ),
+        Span::raw(format!(" {:>3.0}%", percent)),
+    ])
+}
+
+pub fn draw_fleet_grid(
+    f: &mut Frame,
+    hosts: &[Arc<Mutex<FleetHost>>],
+    selected: usize,
+    min_uptime: Option<Duration>,
+    theme: &Theme,
+    notice: Option<&str>,
+) {
+    let area = f.size();
+    let columns = (area.width / FLEET_CELL_WIDTH).max(1) as usize;
+    let visible_rows = ((area.height.saturating_sub(1)) / FLEET_CELL_HEIGHT).max(1) as usize;
+    // Scroll so the selected cell's row is always on screen
+    let first_row = (selected / columns).saturating_sub(visible_rows - 1);
+
+    let mut help = vec![Span::styled("Arrows/hjkl: select  Enter: open dashboard  q: quit", Style::default().fg(theme.muted))];
+    if let Some(notice) = notice {
+        help.push(Span::raw("  "));
+        help.push(Span::styled(notice.to_string(), Style::default().fg(theme.critical)));
+    }
+    let help = Paragraph::new(Line::from(help));
+    f.render_widget(help, Rect { height: 1, ..area });
+
+    for (index, host) in hosts.iter().enumerate().skip(first_row * columns).take(visible_rows * columns) {
+        let host = host.lock().unwrap_or_else(|e| e.into_inner());
+        let row = index / columns - first_row;
+        let column = index % columns;
+        let cell = Rect {
+            x: area.x + column as u16 * FLEET_CELL_WIDTH,
+            y: area.y + 1 + row as u16 * FLEET_CELL_HEIGHT,
+            width: FLEET_CELL_WIDTH.min(area.width),
+            height: FLEET_CELL_HEIGHT,
+        }
+        .intersection(area);
+
+        let (lines, color) = if let Some(error) = &host.error {
+            (vec![Line::from(Span::styled(error.clone(), Style::default().fg(theme.critical)))], theme.critical)
+        } else if !host.sampled {
+            (vec![Line::from(Span::styled("waiting for data...", Style::default().fg(theme.muted)))], theme.muted)
+        } else {
+            let stats = &host.stats;
+            let cpu = Some(stats.cpu_usage).filter(|_| stats.available("cpu"));
+            let memory = Some(stats.memory_percent()).filter(|_| stats.available("memory"));
+            let disk = stats.fullest_disk().map(|(_, percent)| percent);
+            let worst = [cpu, memory, disk].into_iter().flatten().fold(0.0, f64::max);
+            (
+                vec![mini_gauge("CPU", cpu, theme), mini_gauge("MEM", memory, theme), mini_gauge("DISK", disk, theme)],
+                theme.usage(worst),
+            )
+        };
+
+        let mut title = vec![Span::raw(redact(&host.name))];
+        let rebooted = host.sampled && min_uptime.is_some_and(|window| host.stats.booted_within(window));
+        if rebooted {
+            let uptime = host.stats.uptime_seconds.map(format_uptime).unwrap_or_default();
+            title.push(Span::styled(
+                format!(" REBOOTED {} ago ", uptime),
+                Style::default().fg(Color::Black).bg(theme.reboot).add_modifier(Modifier::BOLD),
+            ));
+        }
+        let color = if rebooted && color != theme.critical { theme.reboot } else { color };
+        let mut block = Block::default()
+            .borders(Borders::ALL)
+            .title(Line::from(title))
+            .border_style(Style::default().fg(color));
+        if index == selected {
+            block = block
+                .border_type(BorderType::Thick)
+                .title_style(Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED));
+        }
+        f.render_widget(Paragraph::new(lines).block(block).wrap(Wrap { trim: true }), cell);
+    }
+}
+
+pub async fn monitor_fleet(
+    remotes: Vec<(String, Result<Remote>)>,
+    interval: u64,
+    thresholds: &Thresholds,
+    min_uptime: Option<Duration>,
+    history: usize,
+    mut defaults: DashboardView, // what a host's dashboard starts out as each time it's opened
+    theme_file: Option<PathBuf>,
+) -> Result<()> {
+    let mut theme_watcher = None;
+    if let Some(path) = theme_file {
+        let (watcher, theme) = ThemeWatcher::new(path)?;
+        (theme_watcher, defaults.theme) = (Some(watcher), theme);
+    }
+    let mut theme_error: Option<String> = None;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let hosts: Vec<Arc<Mutex<FleetHost>>> = remotes
+        .into_iter()
+        .map(|(name, remote)| {
+            let host = Arc::new(Mutex::new(FleetHost { name, ..Default::default() }));
+            match remote {
+                Ok(remote) => {
+                    spawn_fleet_sampler(remote, host.clone(), MetricHistory::new(history, interval), stop.clone())
+                }
+                Err(e) => host.lock().unwrap_or_else(|e| e.into_inner()).error = Some(format!("{:#}", e)),
+            }
+            host
+        })
+        .collect();
+    if hosts.is_empty() {
+        anyhow::bail!("No hosts to monitor");
+    }
+
+    let (mut terminal, guard) = setup_terminal(None)?;
+    let mut selected = 0;
+    // Index of the host whose full dashboard is open, if any
+    let mut zoomed: Option<usize> = None;
+    let mut view = defaults;
+
+    loop {
+        match theme_watcher.as_mut().and_then(ThemeWatcher::poll) {
+            Some(Ok(theme)) => (defaults.theme, view.theme, theme_error) = (theme, theme, None),
+            Some(Err(e)) => theme_error = Some(format!("{:#}", e)),
+            None => {}
+        }
+
+        terminal.draw(|f| match zoomed {
+            Some(index) => {
+                let host = hosts[index].lock().unwrap_or_else(|e| e.into_inner());
+                let alerts = check_thresholds(&host.stats, thresholds);
+                let help = format!("[{}] (Esc: back, q: quit, a: units, g: chart, Tab: focus, j/k: scroll, s: sort)", redact(&host.name));
+                let notice = host.error.as_deref().or(theme_error.as_deref());
+                draw_dashboard(f, &host.stats, &host.history, &alerts, notice, &help, &view);
+            }
+            None => draw_fleet_grid(f, &hosts, selected, min_uptime, &defaults.theme, theme_error.as_deref()),
+        })?;
+
+        if event::poll(Duration::from_millis(200))? {
+            let event = event::read()?;
+            if let Event::Resize(_, _) = event {
+                terminal.autoresize()?;
+            }
+            if let Event::Key(key) = event {
+                let columns = (terminal.size()?.width / FLEET_CELL_WIDTH).max(1) as usize;
+                match (zoomed, key.code) {
+                    (_, KeyCode::Char('q')) => break,
+                    (Some(_), KeyCode::Esc | KeyCode::Backspace) => zoomed = None,
+                    (Some(index), code) => {
+                        let host = hosts[index].lock().unwrap_or_else(|e| e.into_inner());
+                        view.handle_key(code, &host.stats);
+                    }
+                    (None, KeyCode::Enter) => {
+                        zoomed = Some(selected);
+                        view = defaults;
+                    }
+                    (None, KeyCode::Left | KeyCode::Char('h')) => selected = selected.saturating_sub(1),
+                    (None, KeyCode::Right | KeyCode::Char('l')) => selected = (selected + 1).min(hosts.len() - 1),
+                    (None, KeyCode::Up | KeyCode::Char('k')) => selected = selected.saturating_sub(columns),
+                    (None, KeyCode::Down | KeyCode::Char('j')) => {
+                        selected = (selected + columns).min(hosts.len() - 1)
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    guard.restore()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::healthy_stats;
+
+    // Everything `draw` puts on a 120x24 screen, as one string
+    fn render(draw: impl FnOnce(&mut Frame)) -> String {
+        let mut terminal = Terminal::new(backend::TestBackend::new(120, 24)).unwrap();
+        terminal.draw(draw).unwrap();
+        terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect()
+    }
+
+    // The dashboard for `stats`, without alerts or a notice
+    fn screen(view: &DashboardView, stats: &SystemStats, history: &MetricHistory) -> String {
+        render(|f| draw_dashboard(f, stats, history, &[], None, "", view))
+    }
+
+    #[test]
+    fn diff_marks_lines_that_changed_position_by_position() {
+        assert_eq!(changed_lines(None, "a\nb\n"), [false, false]);
+        assert_eq!(changed_lines(Some("a\nb\nc\n"), "a\nB\n"), [false, true]);
+        assert_eq!(changed_lines(Some("a\n"), "a\nb\n"), [false, true]);
+    }
+
+    #[test]
+    fn dashboard_labels_the_chart_scales() {
+        let view = DashboardView::new(symbols::Marker::Braille, true, Units::Percent, false);
+        let screen = screen(&view, &healthy_stats(), &MetricHistory::new(100, 1));
+        assert!(screen.contains("100%"), "{}", screen);
+        assert!(screen.contains("under 60%"), "{}", screen);
+    }
+
+    #[test]
+    fn units_key_switches_between_percentages_and_sizes() {
+        let stats = healthy_stats();
+        let history = MetricHistory::new(100, 1);
+        let mut view = DashboardView::new(symbols::Marker::Braille, false, Units::Percent, false);
+        let percentages = screen(&view, &stats, &history);
+        assert!(percentages.contains("/data: 95% of 186.26 GiB"), "{}", percentages);
+
+        assert!(view.handle_key(KeyCode::Char('a'), &stats));
+        let sizes = screen(&view, &stats, &history);
+        assert!(sizes.contains("1.86 GiB"), "{}", sizes);
+        assert!(sizes.contains("(9.31 GiB free)"), "{}", sizes);
+    }
+
+    #[test]
+    fn chart_key_cycles_through_single_charts() {
+        let stats = healthy_stats();
+        let mut history = MetricHistory::new(100, 1);
+        history.record(&stats);
+        let mut view = DashboardView::new(symbols::Marker::Braille, false, Units::Percent, false);
+        let mut screens = Vec::new();
+        for _ in 0..5 {
+            screens.push(screen(&view, &stats, &history));
+            assert!(view.handle_key(KeyCode::Char('g'), &stats));
+        }
+        assert!(screens[0].contains("CPU Usage: 15.6%") && screens[0].contains("Connections: 5"), "{}", screens[0]);
+        assert!(screens[1].contains("CPU Usage") && !screens[1].contains("Connections:"), "{}", screens[1]);
+        assert!(screens[2].contains("Memory Usage: 25.0%"), "{}", screens[2]);
+        assert!(screens[3].contains("Load (1m): 0.52 / 4 cores") && !screens[3].contains("CPU Usage"), "{}", screens[3]);
+        assert!(screens[4].contains("Connections: 5 established"), "{}", screens[4]);
+        assert_eq!(view.chart, None);
+    }
+
+    #[test]
+    fn theme_file_is_reloaded_when_it_changes() {
+        let path = std::env::temp_dir().join(format!("remote_management_theme_{}.toml", std::process::id()));
+        let write = |contents: &str, modified: SystemTime| {
+            std::fs::write(&path, contents).unwrap();
+            std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        };
+        let start = SystemTime::now();
+
+        write("ok = \"#00ff00\"\ncritical = \"magenta\"\n", start);
+        let (mut watcher, theme) = ThemeWatcher::new(path.clone()).unwrap();
+        assert_eq!(theme.ok, Color::Rgb(0, 255, 0));
+        assert_eq!(theme.usage(95.0), Color::Magenta);
+        assert_eq!(theme.warn, Theme::default().warn);
+        assert!(watcher.poll().is_none());
+
+        write("critical = \"not-a-color\"\n", start + Duration::from_secs(1));
+        let error = watcher.poll().unwrap().unwrap_err().to_string();
+        assert!(error.contains("critical = \"not-a-color\" is not a color"), "{}", error);
+
+        write("accent = \"208\"\n", start + Duration::from_secs(2));
+        assert_eq!(watcher.poll().unwrap().unwrap().accent, Color::Indexed(208));
+        std::fs::remove_file(&path).unwrap();
+        assert!(watcher.poll().is_none());
+    }
+
+    #[test]
+    fn session_summary_counts_each_breach_once_until_it_clears() {
+        let mut summary = SessionSummary::new("web1");
+        let mut stats = SystemStats { cpu_usage: 40.0, memory_total: 100, memory_used: 50, ..Default::default() };
+        let cpu = |value: f64| Alert { metric: "cpu".to_string(), value, threshold: 90.0 };
+        let mut breached = Vec::new();
+        for (usage, alerts) in [(40.0, vec![]), (95.0, vec![cpu(95.0)]), (97.0, vec![cpu(97.0)]), (50.0, vec![]), (92.0, vec![cpu(92.0)])] {
+            stats.cpu_usage = usage;
+            summary.record(&stats, &alerts, &breached);
+            breached = alerts.iter().map(|a| a.metric.clone()).collect();
+        }
+        let text = summary.to_string();
+        assert!(text.starts_with("Monitored web1 for 0s: 5 samples\n"), "{}", text);
+        assert!(text.contains("Peak CPU: 97.0%\nPeak memory: 50.0%\n"), "{}", text);
+        assert!(text.ends_with("Threshold breaches: cpu 2 times (peak 97.0%)"), "{}", text);
+    }
+
+    #[test]
+    fn compact_dashboard_shows_load_against_the_core_count() {
+        let view = DashboardView::new(symbols::Marker::Braille, false, Units::Percent, true);
+        let screen = screen(&view, &healthy_stats(), &MetricHistory::new(100, 1));
+        assert!(screen.contains("13% (0.52 / 4 cores)"), "{}", screen);
+        assert!(!screen.contains("100%"), "{}", screen);
+    }
+
+    #[test]
+    fn replay_shows_each_sample_with_the_history_before_it() {
+        let sample = |timestamp, cpu| StoredSample { timestamp, cpu: Some(cpu), memory: Some(42.5), disk: None, load: None };
+        let samples: Vec<StoredSample> = (0..5).map(|i| sample(1000 + i * 30, i as f64 * 10.0)).collect();
+
+        let (stats, history) = replay_frame(&samples, 3, 2);
+        assert_eq!(stats.cpu_usage, 30.0);
+        assert!((stats.memory_percent() - 42.5).abs() < 1e-9);
+        assert!(!stats.available("load"));
+        assert_eq!(history.cpu, [20.0, 30.0]);
+        assert_eq!(history.interval, 30);
+
+        let mut state = ReplayState { position: 0, speed: 10.0, paused: false };
+        assert!(state.handle_key(KeyCode::End, samples.len()));
+        assert!(state.handle_key(KeyCode::Right, samples.len()));
+        assert_eq!(state.position, 4);
+        state.handle_key(KeyCode::PageUp, samples.len());
+        state.handle_key(KeyCode::Char(' '), samples.len());
+        state.handle_key(KeyCode::Char('+'), samples.len());
+        assert_eq!(state, ReplayState { position: 3, speed: 20.0, paused: true });
+        assert!(!state.handle_key(KeyCode::Char('q'), samples.len()));
+
+        let view = DashboardView::new(symbols::Marker::Braille, false, Units::Percent, false);
+        let screen = render(|f| draw_replay(f, "web1", &samples, &state, &history, &stats, &view));
+        assert!(screen.contains("sample 4 of 5  20x (paused)"), "{}", screen);
+        assert!(screen.contains("Memory Usage: 42.5%"), "{}", screen);
+        assert!(screen.contains("1m 30s of 2m"), "{}", screen);
+    }
+
+    #[test]
+    fn interval_keys_step_within_bounds() {
+        assert_eq!(step_interval(1, true), 1);
+        assert_eq!(step_interval(1, false), 2);
+        assert_eq!(step_interval(10, true), 5);
+        // An --interval off the steps moves to its neighbours
+        assert_eq!((step_interval(3, true), step_interval(3, false)), (2, 5));
+        assert_eq!(step_interval(300, false), 300);
+        assert_eq!((step_interval(600, true), step_interval(600, false)), (300, 600));
+    }
+
+    #[test]
+    fn alerts_fire_on_crossing_unless_cooling_down() {
+        let cpu = Alert { metric: "cpu".to_string(), value: 95.0, threshold: 90.0 };
+        let memory = Alert { metric: "memory".to_string(), value: 92.0, threshold: 90.0 };
+        let cooldown = Some(Duration::from_secs(600));
+        let cpu_only = [cpu.clone()];
+        let mut fired = Vec::new();
+        let metrics = |alerts: Vec<Alert>| alerts.into_iter().map(|alert| alert.metric).collect::<Vec<_>>();
+
+        assert_eq!(metrics(firing(&cpu_only, &[], &mut fired, cooldown)), ["cpu"]);
+        // Still over the threshold: already reported
+        assert_eq!(metrics(firing(&[cpu, memory], &["cpu".to_string()], &mut fired, cooldown)), ["memory"]);
+        // Dropped below and crossed again within the cooldown: suppressed
+        assert!(firing(&cpu_only, &[], &mut fired, cooldown).is_empty());
+        // Without a cooldown every crossing fires
+        assert_eq!(metrics(firing(&cpu_only, &[], &mut fired, None)), ["cpu"]);
+        // Once the window has passed it fires again
+        fired[0].1 = Instant::now() - Duration::from_secs(601);
+        assert_eq!(metrics(firing(&cpu_only, &[], &mut fired, cooldown)), ["cpu"]);
+    }
+
+    #[test]
+    fn plain_stats_lists_each_metric_on_its_own_line() {
+        let mut stats = healthy_stats();
+        stats.readonly_mounts.push(crate::stats::ReadonlyMount {
+            mount: "/srv".to_string(),
+            device: "/dev/sdb1".to_string(),
+            fstype: "xfs".to_string(),
+        });
+        let alert = Alert { metric: "disk:/data".to_string(), value: 95.0, threshold: 90.0 };
+        let text = plain_stats("web1", &stats, &[alert], None);
+        assert!(text.starts_with("web1 at "), "{}", text);
+        assert!(text.contains("\nCPU     15.6% (steal 0.0%)\n"), "{}", text);
+        assert!(text.contains("\nLoad    0.52 0.58 0.59 (4 cores)\n"), "{}", text);
+        assert!(text.contains("\nMemory  1.86 GiB / 7.45 GiB (25%)\n"), "{}", text);
+        assert!(text.contains("\nDisk    /data 176.95 GiB / 186.26 GiB (95%)\n"), "{}", text);
+        assert!(text.contains("\nConns   5 established\n"), "{}", text);
+        assert!(text.ends_with("ALERT   /srv is read-only (xfs on /dev/sdb1)\nALERT   disk:/data 95% (threshold 90%)\n"), "{}", text);
+    }
+}